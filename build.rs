@@ -1,6 +1,7 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::configure()
-        .build_server(false)
+        // The server side is only needed for the mock server
+        .build_server(std::env::var_os("CARGO_FEATURE_TEST_UTIL").is_some())
         .compile(&["proto/api.proto"], &["proto"])?;
     Ok(())
 }
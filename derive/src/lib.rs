@@ -0,0 +1,140 @@
+//! Derive macros for [velociraptor_api](https://docs.rs/velociraptor_api)
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+/// Derive `serde::Deserialize` for a struct that represents a row of
+/// artifact or query results.
+///
+/// Field names are mapped to Velociraptor's PascalCase column names
+/// unless a different convention is chosen with
+/// `#[artifact(rename_all = "...")]` on the struct.
+/// `#[artifact(default)]` on the struct applies to all fields. Fields
+/// accept the following attributes:
+///
+/// - `#[artifact(rename = "Column")]`: read the field from `Column`
+/// - `#[artifact(alias = "Column")]`: also accept `Column`, e.g. a name
+///   used by older server versions
+/// - `#[artifact(default)]`: use the default value if the column is
+///   missing or `null`
+/// - `#[artifact(timestamp)]`: parse a timestamp in any encoding
+///   accepted by `VrTimestamp` into a `time::OffsetDateTime`
+/// - `#[artifact(base64)]`: decode a base64-encoded string into a
+///   `Vec<u8>`
+#[proc_macro_derive(ArtifactRow, attributes(artifact))]
+pub fn derive_artifact_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "ArtifactRow does not support generic structs",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "ArtifactRow requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "ArtifactRow can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut rename_all = LitStr::new("PascalCase", name.span());
+    let mut all_default = false;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("artifact")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename_all") {
+                rename_all = meta.value()?.parse()?;
+                Ok(())
+            } else if meta.path.is_ident("default") {
+                all_default = true;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported artifact attribute"))
+            }
+        })?;
+    }
+
+    let mut raw_fields = vec![];
+    let mut idents = vec![];
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let mut serde_attrs = vec![];
+        let mut default = all_default;
+        let mut deserialize_with = None;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("artifact")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    let column: LitStr = meta.value()?.parse()?;
+                    serde_attrs.push(quote!(rename = #column));
+                } else if meta.path.is_ident("alias") {
+                    let column: LitStr = meta.value()?.parse()?;
+                    serde_attrs.push(quote!(alias = #column));
+                } else if meta.path.is_ident("default") {
+                    default = true;
+                } else if meta.path.is_ident("timestamp") {
+                    deserialize_with = Some("::velociraptor_api::row::timestamp");
+                } else if meta.path.is_ident("base64") {
+                    deserialize_with = Some("::velociraptor_api::row::base64");
+                } else {
+                    return Err(meta.error("unsupported artifact attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        if default {
+            serde_attrs.push(quote!(default));
+        }
+        // Defaults for null values are only substituted for fields
+        // without a decoder of their own
+        let deserialize_with =
+            deserialize_with.or(default.then_some("::velociraptor_api::row::null_as_default"));
+        if let Some(path) = deserialize_with {
+            serde_attrs.push(quote!(deserialize_with = #path));
+        }
+        let serde_attrs = serde_attrs.iter().map(|a| quote!(#[serde(#a)]));
+        raw_fields.push(quote!(#(#serde_attrs)* #ident: #ty));
+        idents.push(ident);
+    }
+
+    let raw = format_ident!("__{}Row", name);
+    Ok(quote! {
+        const _: () = {
+            use ::velociraptor_api::row::serde;
+
+            #[derive(serde::Deserialize)]
+            #[serde(crate = "::velociraptor_api::row::serde", rename_all = #rename_all)]
+            struct #raw {
+                #(#raw_fields,)*
+            }
+
+            impl<'de> serde::Deserialize<'de> for #name {
+                fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                    let row = <#raw as serde::Deserialize>::deserialize(d)?;
+                    Ok(#name {
+                        #(#idents: row.#idents,)*
+                    })
+                }
+            }
+        };
+    })
+}
@@ -0,0 +1,106 @@
+//! Dispatch of alerts raised through the VQL `alert()` function
+
+use std::future::Future;
+use std::sync::Arc;
+
+use futures::future::{join_all, BoxFuture};
+use futures::stream::StreamExt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{APIClient, APIClientError, VrTimestamp};
+
+/// Event artifact that alerts are published to by default
+pub const ALERT_ARTIFACT: &str = "Server.Internal.Alerts";
+
+/// An alert raised by a client or server artifact
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Alert {
+    /// Client that raised the alert, `server` for server artifacts
+    pub client_id: String,
+    /// Name passed to `alert()`
+    pub name: String,
+    /// Time of the alert
+    pub event_time: VrTimestamp,
+    /// Artifact that raised the alert
+    pub artifact: String,
+    pub artifact_type: String,
+    /// Additional details passed to `alert()`
+    pub details: serde_json::Value,
+}
+
+type Handler = Arc<dyn Fn(Alert) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Subscribes to alert events and passes them on to async handlers
+pub struct AlertDispatcher {
+    artifact: String,
+    handlers: Vec<(Option<String>, Handler)>,
+}
+
+impl Default for AlertDispatcher {
+    fn default() -> Self {
+        Self {
+            artifact: ALERT_ARTIFACT.to_string(),
+            handlers: vec![],
+        }
+    }
+}
+
+impl AlertDispatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to a different event artifact
+    pub fn artifact(mut self, artifact: &str) -> Self {
+        self.artifact = artifact.to_string();
+        self
+    }
+
+    /// Add a handler for alerts with the given name
+    pub fn on<F, Fut>(mut self, name: &str, handler: F) -> Self
+    where
+        F: Fn(Alert) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.handlers.push((
+            Some(name.to_string()),
+            Arc::new(move |a| Box::pin(handler(a))),
+        ));
+        self
+    }
+
+    /// Add a handler for all alerts
+    pub fn on_any<F, Fut>(mut self, handler: F) -> Self
+    where
+        F: Fn(Alert) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.handlers
+            .push((None, Arc::new(move |a| Box::pin(handler(a)))));
+        self
+    }
+
+    /// Subscribe to alert events and dispatch them until the
+    /// subscription ends. Handlers for a single alert run
+    /// concurrently; the next alert is processed once they are done.
+    pub async fn run(&self, api_client: &APIClient) -> Result<(), APIClientError> {
+        let alerts = api_client
+            .watch_events::<Alert>(&self.artifact, None)
+            .await?;
+        let mut alerts = std::pin::pin!(alerts);
+        while let Some(alert) = alerts.next().await {
+            let alert = alert?;
+            log::debug!("alert {} from {}", alert.name, alert.client_id);
+            join_all(
+                self.handlers
+                    .iter()
+                    .filter(|(name, _)| name.as_ref().is_none_or(|n| n == &alert.name))
+                    .map(|(_, handler)| handler(alert.clone())),
+            )
+            .await;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use std::future::Future;
+
+use serde::de::DeserializeOwned;
+
+use crate::{APIClient, APIClientError, QueryOptions, VfsPath};
+
+/// The main operations of [`APIClient`], as a trait, so that
+/// applications can substitute a fake implementation in their tests
+/// instead of connecting to a live server.
+pub trait VelociraptorApi: Send + Sync {
+    /// Issue a server-side VQL query, see [`APIClient::sync_query`]
+    fn sync_query<T: DeserializeOwned + Send>(
+        &self,
+        query: &str,
+        options: &QueryOptions,
+    ) -> impl Future<Output = Result<Vec<T>, APIClientError>> + Send;
+
+    /// Fetch a file from the server's filestore, see [`APIClient::fetch`]
+    fn fetch(&self, path: VfsPath) -> impl Future<Output = Result<Vec<u8>, APIClientError>> + Send;
+
+    /// Collect an artifact on a client, wait for the collection to
+    /// finish, and return its results
+    fn collect<T: DeserializeOwned + Send>(
+        &self,
+        client_id: &str,
+        artifact: &str,
+        params: &[(String, String)],
+    ) -> impl Future<Output = Result<Vec<T>, APIClientError>> + Send;
+}
+
+impl VelociraptorApi for APIClient {
+    async fn sync_query<T: DeserializeOwned + Send>(
+        &self,
+        query: &str,
+        options: &QueryOptions,
+    ) -> Result<Vec<T>, APIClientError> {
+        APIClient::sync_query(self, query, options).await
+    }
+
+    async fn fetch(&self, path: VfsPath) -> Result<Vec<u8>, APIClientError> {
+        APIClient::fetch(self, path).await
+    }
+
+    async fn collect<T: DeserializeOwned + Send>(
+        &self,
+        client_id: &str,
+        artifact: &str,
+        params: &[(String, String)],
+    ) -> Result<Vec<T>, APIClientError> {
+        let client = self.new_client_unchecked(client_id);
+        let flow = client.collect(artifact, params).await?;
+        flow.wait().await?;
+        flow.results().await
+    }
+}
@@ -0,0 +1,193 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use futures::stream::StreamExt;
+
+use time::OffsetDateTime;
+
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+
+use typed_builder::TypedBuilder;
+
+use crate::{APIClient, APIClientError, EventCursor};
+
+/// Options for archiving event rows to JSONL files
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct ArchiveOptions {
+    /// Directory in which JSONL files and resume markers are written
+    #[builder(setter(into))]
+    dir: PathBuf,
+    /// Start a new file once the current file has reached this size
+    #[builder(default = 64 * 1024 * 1024)]
+    max_size: u64,
+    /// Start a new file once the current file has reached this age
+    #[builder(default)]
+    max_age: Option<Duration>,
+}
+
+/// Writer for size- or time-rotated JSONL files.
+///
+/// Files are named `<artifact>-<unix timestamp>.jsonl`. The position of
+/// the most recent row is recorded in `<artifact>.resume` (at most once
+/// per second, when a new file is started, and when the archiver is
+/// closed), so that archiving can continue where it left off. Rows
+/// written shortly before an interruption may be archived twice.
+///
+/// [`close`](Self::close) must be called to flush the last rows and
+/// record their position; dropping the archiver does neither.
+pub struct EventArchiver {
+    options: ArchiveOptions,
+    artifact: String,
+    file: Option<(File, Instant, u64)>,
+    cursor: EventCursor,
+    marker_written: Option<Instant>,
+    marker_dirty: bool,
+}
+
+impl EventArchiver {
+    pub fn new(artifact: &str, options: ArchiveOptions) -> Self {
+        Self {
+            options,
+            artifact: artifact.to_string(),
+            file: None,
+            cursor: EventCursor::new(),
+            marker_written: None,
+            marker_dirty: false,
+        }
+    }
+
+    fn marker_path(&self) -> PathBuf {
+        self.options.dir.join(format!("{}.resume", self.artifact))
+    }
+
+    /// Load the position of the last row that has been archived, if
+    /// any. Rows up to that position are skipped by `write`.
+    pub async fn resume(&mut self) -> Option<EventCursor> {
+        let marker = tokio::fs::read_to_string(self.marker_path()).await.ok()?;
+        let cursor: EventCursor = serde_json::from_str(&marker).ok()?;
+        self.cursor = cursor.clone();
+        Some(cursor)
+    }
+
+    fn marker(&self) -> String {
+        serde_json::to_string(&self.cursor).unwrap()
+    }
+
+    async fn write_marker(&mut self) -> std::io::Result<()> {
+        if let Some((file, _, _)) = &mut self.file {
+            file.flush().await?;
+        }
+        tokio::fs::write(self.marker_path(), self.marker()).await?;
+        self.marker_written = Some(Instant::now());
+        self.marker_dirty = false;
+        Ok(())
+    }
+
+    async fn rotate(&mut self) -> std::io::Result<()> {
+        if self.marker_dirty {
+            self.write_marker().await?;
+        }
+        if let Some((mut file, _, _)) = self.file.take() {
+            file.flush().await?;
+        }
+        tokio::fs::create_dir_all(&self.options.dir).await?;
+        let name = format!(
+            "{}-{}.jsonl",
+            self.artifact,
+            OffsetDateTime::now_utc().unix_timestamp()
+        );
+        log::debug!("Starting new archive file {name}");
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.options.dir.join(name))
+            .await?;
+        self.file = Some((file, Instant::now(), 0));
+        Ok(())
+    }
+
+    /// Append a row, starting a new file if necessary. Rows that have
+    /// already been archived are skipped.
+    pub async fn write(&mut self, row: &serde_json::Value) -> std::io::Result<()> {
+        if !self.cursor.is_new(row) {
+            return Ok(());
+        }
+        let needs_rotation = match &self.file {
+            None => true,
+            Some((_, opened, size)) => {
+                *size >= self.options.max_size
+                    || self
+                        .options
+                        .max_age
+                        .is_some_and(|age| opened.elapsed() >= age)
+            }
+        };
+        if needs_rotation {
+            self.rotate().await?;
+        }
+        let mut line = row.to_string();
+        line.push('\n');
+        if let Some((file, _, size)) = &mut self.file {
+            file.write_all(line.as_bytes()).await?;
+            *size += line.len() as u64;
+        }
+        if row.get("_ts").is_some() {
+            self.marker_dirty = true;
+            if self
+                .marker_written
+                .is_none_or(|t| t.elapsed() >= Duration::from_secs(1))
+            {
+                self.write_marker().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush the current file and record the position of the last row
+    pub async fn close(mut self) -> std::io::Result<()> {
+        if self.marker_dirty {
+            self.write_marker().await?;
+        }
+        if let Some((mut file, _, _)) = self.file.take() {
+            file.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+impl APIClient {
+    /// Archive rows of a server event artifact to rotated JSONL files.
+    ///
+    /// If a resume marker exists, rows stored since then are archived
+    /// first. The subscription is re-established after losing the
+    /// connection, so this only returns on errors, or when the client's
+    /// [`RetryPolicy`](crate::RetryPolicy) limits the number of
+    /// retries.
+    pub async fn archive_events(
+        &self,
+        artifact: &str,
+        options: ArchiveOptions,
+    ) -> Result<(), APIClientError> {
+        let mut archiver = EventArchiver::new(artifact, options);
+        let cursor = match archiver.resume().await {
+            Some(cursor) => {
+                log::debug!("Resuming {artifact} from {:?}", cursor.ts());
+                cursor
+            }
+            None => EventCursor::new(),
+        };
+        let rows = self.follow_server_events(artifact, cursor);
+        let mut rows = std::pin::pin!(rows);
+        let result = async {
+            while let Some(row) = rows.next().await {
+                archiver.write(&row?).await.map_err(APIClientError::IO)?;
+            }
+            Ok(())
+        }
+        .await;
+        let closed = archiver.close().await;
+        result?;
+        closed.map_err(APIClientError::IO)
+    }
+}
@@ -0,0 +1,597 @@
+use std::collections::{HashMap, HashSet};
+
+use base64::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::row::null_as_default;
+use crate::{APIClient, APIClientError, QueryOptions};
+
+/// Type of an artifact, determining where and how it is run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ArtifactType {
+    #[default]
+    #[serde(rename = "client", alias = "CLIENT", alias = "")]
+    Client,
+    #[serde(rename = "server", alias = "SERVER")]
+    Server,
+    #[serde(rename = "client_event", alias = "CLIENT_EVENT")]
+    ClientEvent,
+    #[serde(rename = "server_event", alias = "SERVER_EVENT")]
+    ServerEvent,
+    #[serde(rename = "notebook", alias = "NOTEBOOK")]
+    Notebook,
+}
+
+impl ArtifactType {
+    /// The type name as used by the server
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Client => "client",
+            Self::Server => "server",
+            Self::ClientEvent => "client_event",
+            Self::ServerEvent => "server_event",
+            Self::Notebook => "notebook",
+        }
+    }
+}
+
+impl std::str::FromStr for ArtifactType {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "client" => Ok(Self::Client),
+            "server" => Ok(Self::Server),
+            "client_event" => Ok(Self::ClientEvent),
+            "server_event" => Ok(Self::ServerEvent),
+            "notebook" => Ok(Self::Notebook),
+            _ => Err(format!("unknown artifact type: {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for ArtifactType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A parameter declared by an artifact
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ArtifactParameter {
+    pub name: String,
+    #[serde(default, deserialize_with = "scalar_string")]
+    pub default: String,
+    #[serde(default)]
+    pub description: String,
+    /// Parameter type as declared in the artifact, e.g. `bool`, `int`, `csv`.
+    /// An empty string denotes a plain string parameter.
+    #[serde(default, rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub friendly_name: String,
+    /// Permitted values for parameters of type `choices`
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub choices: Vec<String>,
+}
+
+impl ArtifactParameter {
+    /// Check whether `value` is acceptable for the parameter's type.
+    /// Empty values are accepted for all types except `choices`.
+    pub fn accepts(&self, value: &str) -> bool {
+        let value = value.trim();
+        match self.kind.as_str() {
+            "choices" => self.choices.iter().any(|c| c == value),
+            _ if value.is_empty() => true,
+            "bool" => matches!(
+                value.to_lowercase().as_str(),
+                "y" | "n" | "yes" | "no" | "true" | "false" | "1" | "0"
+            ),
+            "int" | "int64" => value.parse::<i64>().is_ok(),
+            "float" => value.parse::<f64>().is_ok(),
+            "json" => serde_json::from_str::<serde_json::Value>(value).is_ok(),
+            "json_array" => serde_json::from_str::<Vec<serde_json::Value>>(value).is_ok(),
+            _ => true,
+        }
+    }
+}
+
+/// A problem found while validating collection parameters
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParameterError {
+    /// The artifact does not declare a parameter of this name
+    Unknown(String),
+    /// The supplied value does not match the parameter's type
+    InvalidValue {
+        name: String,
+        kind: String,
+        value: String,
+    },
+}
+
+impl std::fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::Unknown(name) => write!(f, "unknown parameter {name}"),
+            Self::InvalidValue { name, kind, value } => {
+                write!(f, "invalid value {value:?} for {kind} parameter {name}")
+            }
+        }
+    }
+}
+
+impl ParameterError {
+    pub(crate) fn join(errors: &[Self]) -> String {
+        errors
+            .iter()
+            .map(Self::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Artifact metadata as returned by the `artifact_definitions()` plugin
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Artifact {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, rename = "type")]
+    pub kind: ArtifactType,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub parameters: Vec<ArtifactParameter>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub required_permissions: Vec<String>,
+}
+
+/// A single source (query) of an artifact
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ArtifactSource {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub precondition: String,
+    #[serde(default)]
+    pub query: String,
+    /// Cells of the notebook that the GUI creates for this source
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub notebook: Vec<ArtifactTemplate>,
+}
+
+/// A report or notebook cell template of an artifact
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ArtifactTemplate {
+    /// `markdown` or `vql` for notebook cells, the kind of report
+    /// (e.g. `CLIENT`, `HUNT`) for reports
+    #[serde(default, rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub template: String,
+}
+
+/// An external tool declared by an artifact
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ArtifactTool {
+    pub name: String,
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub github_project: String,
+    #[serde(default)]
+    pub github_asset_regex: String,
+    #[serde(default)]
+    pub serve_locally: bool,
+}
+
+/// Full artifact definition as parsed from its YAML representation
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ArtifactDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default, rename = "type")]
+    pub kind: ArtifactType,
+    #[serde(default)]
+    pub precondition: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub parameters: Vec<ArtifactParameter>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub sources: Vec<ArtifactSource>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub required_permissions: Vec<String>,
+    /// Artifacts whose exported definitions are imported
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub imports: Vec<String>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub tools: Vec<ArtifactTool>,
+    #[serde(default)]
+    pub export: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub reports: Vec<ArtifactTemplate>,
+    /// The YAML document this definition was parsed from
+    #[serde(skip)]
+    pub raw: String,
+}
+
+impl ArtifactDefinition {
+    /// Parse an artifact definition from YAML
+    pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
+        let mut def: Self = serde_yaml::from_str(yaml)?;
+        def.raw = yaml.to_string();
+        Ok(def)
+    }
+
+    /// Names of artifacts this artifact depends on directly, either
+    /// through `imports` or by calling them as `Artifact.Name()` in
+    /// one of its queries
+    pub fn dependencies(&self) -> Vec<String> {
+        let mut deps = self.imports.clone();
+        let queries = std::iter::once(&self.export)
+            .chain(std::iter::once(&self.precondition))
+            .chain(
+                self.sources
+                    .iter()
+                    .flat_map(|s| [&s.query, &s.precondition]),
+            );
+        for query in queries {
+            for (pos, _) in query.match_indices("Artifact.") {
+                // Skip matches inside longer identifiers such as
+                // `MyArtifact.` or `x.Artifact.`
+                let prev = query[..pos].chars().next_back();
+                if prev.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '.') {
+                    continue;
+                }
+                let name: String = query[pos + 9..]
+                    .chars()
+                    .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '.')
+                    .collect();
+                let name = name.trim_end_matches('.');
+                if !name.is_empty() && name != self.name && !deps.iter().any(|d| d == name) {
+                    deps.push(name.to_string());
+                }
+            }
+        }
+        deps
+    }
+
+    /// Validate collection parameters against the parameters
+    /// declared by the artifact. Parameters that are not supplied are
+    /// not checked, since the server does not require any parameter
+    /// and uses the declared default instead.
+    pub fn validate_parameters(
+        &self,
+        params: &[(String, String)],
+    ) -> Result<(), Vec<ParameterError>> {
+        let errors: Vec<_> = params
+            .iter()
+            .filter_map(
+                |(name, value)| match self.parameters.iter().find(|p| &p.name == name) {
+                    None => Some(ParameterError::Unknown(name.clone())),
+                    Some(p) if !p.accepts(value) => Some(ParameterError::InvalidValue {
+                        name: name.clone(),
+                        kind: p.kind.clone(),
+                        value: value.clone(),
+                    }),
+                    _ => None,
+                },
+            )
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Dependencies of an artifact
+#[derive(Clone, Debug, Default)]
+pub struct ArtifactDependencies {
+    /// Artifacts the artifact depends on, directly or indirectly. Each
+    /// artifact is listed after its own dependencies, so this is the
+    /// order in which they need to be deployed.
+    pub artifacts: Vec<String>,
+    /// Tools declared by the artifact or any of its dependencies
+    pub tools: Vec<ArtifactTool>,
+}
+
+/// Result of server-side artifact verification
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ArtifactVerification {
+    #[serde(default, alias = "Errors", deserialize_with = "null_as_default")]
+    pub errors: Vec<String>,
+    #[serde(default, alias = "Warnings", deserialize_with = "null_as_default")]
+    pub warnings: Vec<String>,
+}
+
+impl ArtifactVerification {
+    /// True if no errors have been reported
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Accept any scalar (string, number, boolean) as string. Artifact
+/// YAML files often contain unquoted numeric or boolean defaults.
+fn scalar_string<'de, D: Deserializer<'de>>(d: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Scalar {
+        String(String),
+        Int(i64),
+        Float(f64),
+        Bool(bool),
+    }
+    Ok(match Option::<Scalar>::deserialize(d)? {
+        Some(Scalar::String(s)) => s,
+        Some(Scalar::Int(i)) => i.to_string(),
+        Some(Scalar::Float(f)) => f.to_string(),
+        Some(Scalar::Bool(b)) => b.to_string(),
+        None => String::new(),
+    })
+}
+
+impl APIClient {
+    /// List all artifact definitions known to the server
+    pub async fn artifacts(&self) -> Result<Vec<Artifact>, APIClientError> {
+        self.sync_query(
+            r#"SELECT name, description, type, parameters, required_permissions
+               FROM artifact_definitions()"#,
+            &QueryOptions::builder().build(),
+        )
+        .await
+    }
+
+    /// Search artifact definitions whose name or description contain
+    /// `term` (case-insensitive), optionally restricted to one type
+    pub async fn search_artifacts(
+        &self,
+        term: &str,
+        type_filter: Option<ArtifactType>,
+    ) -> Result<Vec<Artifact>, APIClientError> {
+        let term = term.chars().fold(String::from("(?i)"), |mut acc, c| {
+            if "\\.+*?()|[]{}^$".contains(c) {
+                acc.push('\\');
+            }
+            acc.push(c);
+            acc
+        });
+        let kind = type_filter.map(|t| t.as_str()).unwrap_or_default();
+        self.sync_query(
+            r#"SELECT name, description, type, parameters, required_permissions
+               FROM artifact_definitions()
+               WHERE (name =~ term OR description =~ term)
+                 AND (kind = "" OR type = kind)"#,
+            &QueryOptions::builder()
+                .env(vec![("term".into(), term), ("kind".into(), kind.into())])
+                .build(),
+        )
+        .await
+    }
+
+    /// Fetch a single artifact definition by name. The raw YAML
+    /// document is available in the `raw` field of the result.
+    pub async fn artifact(&self, name: &str) -> Result<ArtifactDefinition, APIClientError> {
+        #[derive(Deserialize)]
+        struct Raw {
+            raw: String,
+        }
+        let result: Vec<Raw> = self
+            .sync_query(
+                r#"SELECT raw FROM artifact_definitions(names=name)"#,
+                &QueryOptions::builder()
+                    .env(vec![("name".into(), name.into())])
+                    .build(),
+            )
+            .await?;
+        let raw = result
+            .into_iter()
+            .next()
+            .ok_or_else(|| APIClientError::UnknownArtifact(name.into()))?
+            .raw;
+        ArtifactDefinition::from_yaml(&raw).map_err(APIClientError::MalformedArtifact)
+    }
+
+    /// Add a custom artifact to the server or replace an existing one
+    /// with the given YAML definition
+    pub async fn set_artifact(&self, yaml: &str) -> Result<Artifact, APIClientError> {
+        #[derive(Deserialize)]
+        struct SetResult {
+            artifact: Option<Artifact>,
+        }
+        let result: Vec<SetResult> = self
+            .sync_query(
+                r#"SELECT artifact_set(definition=definition) AS artifact FROM scope()"#,
+                &QueryOptions::builder()
+                    .env(vec![("definition".into(), yaml.into())])
+                    .build(),
+            )
+            .await?;
+        result
+            .into_iter()
+            .next()
+            .and_then(|r| r.artifact)
+            .ok_or_else(|| APIClientError::VQL("artifact_set() failed".into()))
+    }
+
+    /// Have the server parse and compile an artifact definition
+    /// without saving it
+    pub async fn verify_artifact(
+        &self,
+        yaml: &str,
+    ) -> Result<ArtifactVerification, APIClientError> {
+        #[derive(Deserialize)]
+        struct VerifyResult {
+            result: Option<ArtifactVerification>,
+        }
+        let result: Vec<VerifyResult> = self
+            .sync_query(
+                r#"SELECT verify(artifact=definition) AS result FROM scope()"#,
+                &QueryOptions::builder()
+                    .env(vec![("definition".into(), yaml.into())])
+                    .build(),
+            )
+            .await?;
+        result
+            .into_iter()
+            .next()
+            .and_then(|r| r.result)
+            .ok_or_else(|| APIClientError::VQL("verify() failed".into()))
+    }
+
+    /// Import all artifact definitions contained in a zip file, such
+    /// as an artifact exchange pack
+    pub async fn import_artifact_pack(&self, zip: &[u8]) -> Result<Vec<Artifact>, APIClientError> {
+        #[derive(Deserialize)]
+        struct SetResult {
+            artifact: Option<Artifact>,
+        }
+        let result: Vec<SetResult> = self
+            .sync_query(
+                r#"LET pack <= base64decode(string=pack_b64)
+                   SELECT artifact_set(
+                            definition=read_file(filename=OSPath, accessor="zip"))
+                          AS artifact
+                   FROM glob(globs="**/*.{yaml,yml}",
+                             root=pathspec(DelegateAccessor="data", DelegatePath=pack),
+                             accessor="zip")"#,
+                &QueryOptions::builder()
+                    .env(vec![("pack_b64".into(), BASE64_STANDARD.encode(zip))])
+                    .build(),
+            )
+            .await?;
+        Ok(result.into_iter().filter_map(|r| r.artifact).collect())
+    }
+
+    /// Remove a custom artifact from the server
+    pub async fn delete_artifact(&self, name: &str) -> Result<(), APIClientError> {
+        self.sync_query::<serde_json::Value>(
+            r#"SELECT artifact_delete(name=name) FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![("name".into(), name.into())])
+                .build(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Determine the artifacts and tools an artifact depends on
+    pub async fn artifact_dependencies(
+        &self,
+        name: &str,
+    ) -> Result<ArtifactDependencies, APIClientError> {
+        #[derive(Deserialize)]
+        struct Raw {
+            raw: String,
+        }
+        let definitions = self
+            .sync_query::<Raw>(
+                r#"SELECT raw FROM artifact_definitions(names=name, deps=TRUE)"#,
+                &QueryOptions::builder()
+                    .env(vec![("name".into(), name.into())])
+                    .build(),
+            )
+            .await?
+            .into_iter()
+            .map(|r| ArtifactDefinition::from_yaml(&r.raw).map(|d| (d.name.clone(), d)))
+            .collect::<Result<HashMap<_, _>, _>>()
+            .map_err(APIClientError::MalformedArtifact)?;
+        if !definitions.contains_key(name) {
+            return Err(APIClientError::UnknownArtifact(name.into()));
+        }
+
+        fn visit(
+            name: &str,
+            definitions: &HashMap<String, ArtifactDefinition>,
+            seen: &mut HashSet<String>,
+            result: &mut ArtifactDependencies,
+        ) {
+            if !seen.insert(name.to_string()) {
+                return;
+            }
+            if let Some(def) = definitions.get(name) {
+                for dep in def.dependencies() {
+                    visit(&dep, definitions, seen, result);
+                }
+                for tool in &def.tools {
+                    if !result.tools.iter().any(|t| t.name == tool.name) {
+                        result.tools.push(tool.clone());
+                    }
+                }
+            }
+            result.artifacts.push(name.to_string());
+        }
+
+        let mut result = ArtifactDependencies::default();
+        visit(name, &definitions, &mut HashSet::new(), &mut result);
+        result.artifacts.pop();
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameter(kind: &str) -> ArtifactParameter {
+        ArtifactParameter {
+            name: "P".into(),
+            kind: kind.into(),
+            choices: vec!["A".into(), "B".into()],
+            ..ArtifactParameter::default()
+        }
+    }
+
+    #[test]
+    fn accepts() {
+        let cases = [
+            ("", "anything", true),
+            ("bool", "Y", true),
+            ("bool", "false", true),
+            ("bool", "maybe", false),
+            ("int", " 42 ", true),
+            ("int64", "-1", true),
+            ("int", "4.2", false),
+            ("float", "4.2", true),
+            ("float", "four", false),
+            ("json", r#"{"a": 1}"#, true),
+            ("json", "{", false),
+            ("json_array", "[1, 2]", true),
+            ("json_array", r#"{"a": 1}"#, false),
+            ("choices", "A", true),
+            ("choices", "C", false),
+            ("choices", "", false),
+            ("int", "", true),
+        ];
+        for (kind, value, expected) in cases {
+            assert_eq!(
+                parameter(kind).accepts(value),
+                expected,
+                "{kind} parameter with value {value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn dependencies() {
+        let def = ArtifactDefinition::from_yaml(
+            r#"
+name: Custom.Test
+imports:
+  - Custom.Imported
+sources:
+  - query: |
+      SELECT * FROM Artifact.Generic.Client.Info(),
+        MyArtifact.Other(), x.Artifact.Nested(), Artifact.Custom.Test()
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            def.dependencies(),
+            ["Custom.Imported", "Generic.Client.Info"]
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use serde::Deserialize;
+
+use tokio::io::AsyncWrite;
+
+use crate::{APIClient, APIClientError, QueryOptions, VfsPath};
+
+impl APIClient {
+    /// Have the server write a backup of its state, including clients,
+    /// hunts, and custom artifact definitions, to a zip archive in the
+    /// filestore. Returns the archive's path.
+    pub async fn create_backup(&self, name: &str) -> Result<VfsPath, APIClientError> {
+        #[derive(Deserialize)]
+        struct Row {
+            backup: Option<serde_json::Value>,
+        }
+        let name = match name.ends_with(".zip") {
+            true => name.to_string(),
+            false => format!("{name}.zip"),
+        };
+        self.sync_query::<Row>(
+            r#"SELECT backup(name=name) AS backup FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![("name".into(), name.clone())])
+                .build(),
+        )
+        .await?
+        .into_iter()
+        .next()
+        .and_then(|r| r.backup)
+        .ok_or_else(|| APIClientError::VQL("backup() failed".into()))?;
+        Ok(VfsPath::backup(&name))
+    }
+
+    /// Create a backup and download it. Returns the number of bytes
+    /// written.
+    pub async fn backup_to<W: AsyncWrite + Unpin>(
+        &self,
+        name: &str,
+        writer: &mut W,
+    ) -> Result<u64, APIClientError> {
+        let path = self.create_backup(name).await?;
+        log::debug!("Fetching backup {path}");
+        self.fetch_to(path, writer).await
+    }
+}
@@ -0,0 +1,120 @@
+//! Synchronous wrapper around the asynchronous API, for programs that
+//! don't use an async runtime themselves.
+//!
+//! [`APIClient`] runs requests on an internal single-threaded tokio
+//! runtime. Its methods must not be called from within an async
+//! context, since blocking the runtime's thread from there panics.
+
+// Errors are the same as those of the asynchronous API
+#![allow(clippy::result_large_err)]
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+
+use crate::{APIClientConfig, APIClientError, QueryOptions, ServerInfo, VfsPath};
+
+/// Blocking client for the Velociraptor gRPC API
+#[derive(Clone)]
+pub struct APIClient {
+    inner: crate::APIClient,
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+impl TryFrom<&APIClientConfig> for APIClient {
+    type Error = APIClientError;
+    fn try_from(cfg: &APIClientConfig) -> Result<Self, Self::Error> {
+        Self::new(crate::APIClient::try_from(cfg)?)
+    }
+}
+
+impl APIClient {
+    /// Wrap an asynchronous client
+    pub fn new(inner: crate::APIClient) -> Result<Self, APIClientError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(APIClientError::IO)?;
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// The wrapped asynchronous client, e.g. for use with
+    /// [`block_on`](Self::block_on)
+    pub fn inner(&self) -> &crate::APIClient {
+        &self.inner
+    }
+
+    /// Run a future on the internal runtime, e.g. one returned by a
+    /// method of the asynchronous client that is not wrapped here
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// See [`crate::APIClient::with_org`]
+    pub fn with_org(&self, org_id: &str) -> APIClient {
+        APIClient {
+            inner: self.inner.with_org(org_id),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// See [`crate::APIClient::with_timeout`]
+    pub fn with_timeout(&self, timeout: Duration) -> APIClient {
+        APIClient {
+            inner: self.inner.with_timeout(timeout),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    /// Issue a server-side VQL query
+    pub fn sync_query<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        options: &QueryOptions,
+    ) -> Result<Vec<T>, APIClientError> {
+        self.block_on(self.inner.sync_query(query, options))
+    }
+
+    /// Fetch downloadable file from Velociraptor server
+    pub fn fetch<P: Into<VfsPath>>(&self, path: P) -> Result<Vec<u8>, APIClientError> {
+        self.block_on(self.inner.fetch(path))
+    }
+
+    /// Upload data read from `reader` to the server's filestore.
+    /// Returns the number of bytes written.
+    pub fn upload<P, R>(&self, path: P, reader: &mut R) -> Result<u64, APIClientError>
+    where
+        P: Into<VfsPath>,
+        R: std::io::Read,
+    {
+        let mut data = vec![];
+        reader.read_to_end(&mut data).map_err(APIClientError::IO)?;
+        self.block_on(self.inner.upload(path, &mut data.as_slice()))
+    }
+
+    /// Version and build information of the connected server
+    pub fn server_info(&self) -> Result<ServerInfo, APIClientError> {
+        self.block_on(self.inner.server_info())
+    }
+
+    /// Collect an artifact on a client, wait for the collection to
+    /// finish, and return its results
+    pub fn collect<T: DeserializeOwned>(
+        &self,
+        client_id: &str,
+        artifact: &str,
+        params: &[(String, String)],
+    ) -> Result<Vec<T>, APIClientError> {
+        self.block_on(async {
+            let client = self.inner.new_client_unchecked(client_id);
+            let flow = client.collect(artifact, params).await?;
+            flow.wait().await?;
+            flow.results().await
+        })
+    }
+}
@@ -0,0 +1,282 @@
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+use tokio::time::Duration;
+
+use tonic::transport::{Endpoint, Uri};
+
+use crate::tls::Connector;
+use crate::{APIClient, APIClientConfig, APIClientError, FixedInterval, PollStrategy, Shared};
+
+/// Name the server certificate is issued for by default
+const DEFAULT_DOMAIN_NAME: &str = "VelociraptorServer";
+
+/// Number of bytes requested per `VFSGetBuffer` call by default
+pub(crate) const DEFAULT_CHUNK_SIZE: u32 = 1024;
+
+/// How operations that recover from connection loss, such as
+/// [`APIClient::follow_client_events`], retry
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay, which doubles with every failed retry
+    pub max_backoff: Duration,
+    /// Number of consecutive failed retries after which the operation
+    /// gives up with the last connection error, or `None` to retry
+    /// forever
+    pub max_retries: Option<u32>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            max_retries: None,
+        }
+    }
+}
+
+/// Compression of requests and responses
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    /// gzip, which must be supported by the server
+    #[cfg(feature = "gzip")]
+    Gzip,
+}
+
+/// How issued VQL queries are logged at debug level. Values passed
+/// in the query environment often include credentials, e.g. when
+/// adding secrets, so they are not logged verbatim by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QueryLogging {
+    /// Don't log queries
+    Off,
+    /// Log queries and the names of environment variables, replacing
+    /// their values with `<redacted>`
+    #[default]
+    Redacted,
+    /// Log queries, replacing environment values with a prefix of
+    /// their SHA-256 hash so that equal values can be recognized
+    Hashed,
+    /// Log queries and environment values verbatim, and result rows
+    /// at trace level
+    Full,
+}
+
+impl QueryLogging {
+    /// Log `vql` as issued with `env`
+    pub(crate) fn log(self, vql: &str, env: &[(String, String)]) {
+        if self == QueryLogging::Off || !log::log_enabled!(log::Level::Debug) {
+            return;
+        }
+        log::debug!("query = {:?} env = [{}]", vql.trim(), self.format_env(env));
+    }
+
+    /// Format `env` for logging, as far as this level allows
+    fn format_env(self, env: &[(String, String)]) -> String {
+        env.iter()
+            .map(|(key, value)| match self {
+                QueryLogging::Full => format!("{key}={value:?}"),
+                QueryLogging::Hashed => {
+                    let hash = format!("{:x}", Sha256::digest(value));
+                    format!("{key}=sha256:{}", &hash[..16])
+                }
+                _ => format!("{key}={}", crate::server::REDACTED),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Builder for an [`APIClient`], created by [`APIClient::builder`]
+pub struct APIClientBuilder<'a> {
+    config: &'a APIClientConfig,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    stream_buffer: Option<u32>,
+    domain_name: Option<String>,
+    chunk_size: u32,
+    retry: RetryPolicy,
+    compression: Compression,
+    poll: Arc<dyn PollStrategy>,
+    query_logging: QueryLogging,
+    org_id: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl APIClient {
+    /// Build a client from `config`, overriding individual settings
+    pub fn builder(config: &APIClientConfig) -> APIClientBuilder<'_> {
+        APIClientBuilder {
+            config,
+            timeout: None,
+            connect_timeout: None,
+            stream_buffer: None,
+            domain_name: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            retry: RetryPolicy::default(),
+            compression: Compression::None,
+            poll: Arc::new(FixedInterval::default()),
+            query_logging: QueryLogging::default(),
+            org_id: None,
+            user_agent: None,
+        }
+    }
+}
+
+impl APIClientBuilder<'_> {
+    /// Timeout for RPCs, flow waits, and result fetches, see
+    /// [`APIClient::with_timeout`]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout for establishing the connection to the server
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Number of bytes of a streamed response that are buffered
+    /// before the server has to wait for them to be consumed, instead
+    /// of 2 MiB. A single response message is always buffered
+    /// completely, so the `max_row` option of
+    /// [`QueryOptions`](crate::QueryOptions) should be limited as well
+    /// when rows are large.
+    pub fn stream_buffer(mut self, bytes: u32) -> Self {
+        self.stream_buffer = Some(bytes);
+        self
+    }
+
+    /// Name used for SNI and for verifying the server certificate,
+    /// instead of `VelociraptorServer`
+    pub fn domain_name(mut self, name: &str) -> Self {
+        self.domain_name = Some(name.to_string());
+        self
+    }
+
+    /// Number of bytes requested per call when downloading files from
+    /// the filestore
+    pub fn chunk_size(mut self, size: u32) -> Self {
+        self.chunk_size = size.max(1);
+        self
+    }
+
+    /// How connection losses are retried
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Compress requests and ask the server to compress responses,
+    /// which saves bandwidth for large result sets
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// How waiting for flows polls the server
+    pub fn poll_strategy<P: PollStrategy + 'static>(mut self, strategy: P) -> Self {
+        self.poll = Arc::new(strategy);
+        self
+    }
+
+    /// How issued queries are logged
+    pub fn query_logging(mut self, logging: QueryLogging) -> Self {
+        self.query_logging = logging;
+        self
+    }
+
+    /// Organization used by default, instead of the one from the
+    /// config
+    pub fn org_id(mut self, org_id: &str) -> Self {
+        self.org_id = Some(org_id.to_string());
+        self
+    }
+
+    /// User agent sent to the server
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Build the client. The connection is established on first use.
+    // Same error type as the rest of the API
+    #[allow(clippy::result_large_err)]
+    pub fn build(self) -> Result<APIClient, APIClientError> {
+        let cfg = self.config;
+        let uri = Uri::builder()
+            .scheme(crate::tls::SCHEME)
+            .authority(cfg.api_connection_string.as_str())
+            .path_and_query("/")
+            .build()
+            .map_err(APIClientError::HTTP)?;
+        let domain_name = self.domain_name.as_deref().unwrap_or(DEFAULT_DOMAIN_NAME);
+        let (mut endpoint, tls) = Connector::new(Endpoint::from(uri), cfg, domain_name)?;
+        if let Some(timeout) = self.timeout {
+            endpoint = endpoint.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            endpoint = endpoint.connect_timeout(timeout);
+        }
+        if let Some(bytes) = self.stream_buffer {
+            endpoint = endpoint.initial_stream_window_size(bytes);
+        }
+        if let Some(user_agent) = self.user_agent {
+            endpoint = endpoint
+                .user_agent(user_agent)
+                .map_err(APIClientError::Transport)?;
+        }
+        Ok(APIClient {
+            shared: Shared::new(endpoint, tls, Default::default()),
+            org_id: self
+                .org_id
+                .or_else(|| cfg.org_id.clone())
+                .filter(|o| !o.is_empty()),
+            timeout: self.timeout,
+            chunk_size: self.chunk_size,
+            retry: self.retry,
+            compression: self.compression,
+            poll: self.poll,
+            query_logging: self.query_logging,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> Vec<(String, String)> {
+        vec![
+            ("name".into(), "alice".into()),
+            ("password".into(), "hunter2".into()),
+        ]
+    }
+
+    #[test]
+    fn redacted_env() {
+        let s = QueryLogging::Redacted.format_env(&env());
+        assert_eq!(s, "name=<redacted>, password=<redacted>");
+        assert!(!s.contains("hunter2"));
+    }
+
+    #[test]
+    fn hashed_env() {
+        let s = QueryLogging::Hashed.format_env(&env());
+        assert!(!s.contains("hunter2"));
+        let hash = format!("{:x}", Sha256::digest("hunter2"));
+        assert!(s.ends_with(&format!("password=sha256:{}", &hash[..16])));
+    }
+
+    #[test]
+    fn full_env() {
+        let s = QueryLogging::Full.format_env(&env());
+        assert_eq!(s, r#"name="alice", password="hunter2""#);
+    }
+}
@@ -0,0 +1,194 @@
+//! Typed request and result types for frequently used built-in artifacts
+
+use serde::de::DeserializeOwned;
+
+use typed_builder::TypedBuilder;
+
+use crate::{APIClientError, ArtifactRow, Client, ClientFlow, VrTimestamp};
+
+/// A built-in artifact with a fixed set of parameters and a known
+/// result row type
+pub trait BuiltinArtifact {
+    /// Name of the artifact
+    const NAME: &'static str;
+    /// Source that produces `Row`, for artifacts with several sources
+    const SOURCE: Option<&'static str> = None;
+    /// Type of the rows produced by the artifact
+    type Row: DeserializeOwned;
+    /// Parameters to be passed to the collection
+    fn parameters(&self) -> Vec<(String, String)>;
+}
+
+/// Name of the artifact and source whose results are read as `A::Row`
+fn results_name<A: BuiltinArtifact>() -> String {
+    match A::SOURCE {
+        Some(source) => format!("{}/{source}", A::NAME),
+        None => A::NAME.to_string(),
+    }
+}
+
+/// `Generic.Client.Info`: Basic information about the client
+#[derive(Clone, Debug, Default)]
+pub struct ClientInfo;
+
+/// Result row of `Generic.Client.Info/BasicInformation`
+#[derive(Clone, Debug, Default, ArtifactRow)]
+pub struct ClientInfoRow {
+    pub name: String,
+    pub version: String,
+    pub build_time: String,
+    pub labels: Option<Vec<String>>,
+    pub hostname: String,
+    #[artifact(rename = "OS")]
+    pub os: String,
+    pub architecture: String,
+    pub platform: String,
+    pub platform_version: String,
+    pub kernel_version: String,
+    pub fqdn: String,
+}
+
+impl BuiltinArtifact for ClientInfo {
+    const NAME: &'static str = "Generic.Client.Info";
+    const SOURCE: Option<&'static str> = Some("BasicInformation");
+    type Row = ClientInfoRow;
+    fn parameters(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+}
+
+/// `Windows.KapeFiles.Targets`: Collect files using KapeFiles target
+/// definitions
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct KapeFilesTargets {
+    /// Targets to collect, e.g. `_SANS_Triage` or `_BasicCollection`
+    #[builder(setter(into))]
+    pub targets: Vec<String>,
+    /// Device to collect from, e.g. `C:`
+    #[builder(default, setter(strip_option, into))]
+    pub device: Option<String>,
+    /// Also collect from volume shadow copies created within the given
+    /// number of days
+    #[builder(default, setter(strip_option))]
+    pub vss_analysis_age: Option<u32>,
+}
+
+/// Result row of `Windows.KapeFiles.Targets/All File Metadata`
+#[derive(Clone, Debug, Default, ArtifactRow)]
+#[artifact(default)]
+pub struct KapeFilesTargetsRow {
+    pub source_file: String,
+    pub size: u64,
+    pub created: VrTimestamp,
+    pub modified: VrTimestamp,
+    pub last_accessed: VrTimestamp,
+}
+
+impl BuiltinArtifact for KapeFilesTargets {
+    const NAME: &'static str = "Windows.KapeFiles.Targets";
+    const SOURCE: Option<&'static str> = Some("All File Metadata");
+    type Row = KapeFilesTargetsRow;
+    fn parameters(&self) -> Vec<(String, String)> {
+        let mut params: Vec<_> = self
+            .targets
+            .iter()
+            .map(|t| (t.clone(), "Y".to_string()))
+            .collect();
+        if let Some(device) = &self.device {
+            params.push(("Device".into(), device.clone()));
+        }
+        if let Some(age) = self.vss_analysis_age {
+            params.push(("VSSAnalysisAge".into(), age.to_string()));
+        }
+        params
+    }
+}
+
+/// `Generic.Forensic.Timeline`: File system timeline below a
+/// directory
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct ForensicTimeline {
+    /// Directory to start from
+    #[builder(setter(into))]
+    pub root: String,
+    /// Accessor used for reading the file system
+    #[builder(default, setter(strip_option, into))]
+    pub accessor: Option<String>,
+}
+
+/// Result row of `Generic.Forensic.Timeline`
+#[derive(Clone, Debug, Default, ArtifactRow)]
+#[artifact(default)]
+pub struct ForensicTimelineRow {
+    #[artifact(rename = "OSPath", alias = "FullPath")]
+    pub path: String,
+    pub size: u64,
+    pub mode: String,
+    pub mtime: VrTimestamp,
+    pub atime: VrTimestamp,
+    pub ctime: VrTimestamp,
+}
+
+impl BuiltinArtifact for ForensicTimeline {
+    const NAME: &'static str = "Generic.Forensic.Timeline";
+    type Row = ForensicTimelineRow;
+    fn parameters(&self) -> Vec<(String, String)> {
+        let mut params = vec![("root".to_string(), self.root.clone())];
+        if let Some(accessor) = &self.accessor {
+            params.push(("Accessor".into(), accessor.clone()));
+        }
+        params
+    }
+}
+
+/// `Windows.Sys.Users`: Local user accounts
+#[derive(Clone, Debug, Default)]
+pub struct WindowsUsers;
+
+/// Result row of `Windows.Sys.Users`
+#[derive(Clone, Debug, Default, ArtifactRow)]
+#[artifact(default)]
+pub struct WindowsUsersRow {
+    pub uid: i64,
+    pub gid: i64,
+    pub name: String,
+    pub description: String,
+    pub directory: String,
+    #[artifact(rename = "UUID")]
+    pub uuid: String,
+    #[artifact(rename = "Type")]
+    pub kind: String,
+}
+
+impl BuiltinArtifact for WindowsUsers {
+    const NAME: &'static str = "Windows.Sys.Users";
+    type Row = WindowsUsersRow;
+    fn parameters(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+}
+
+impl Client<'_> {
+    /// Schedule collection of a built-in artifact
+    pub async fn collect_builtin<A: BuiltinArtifact>(
+        &self,
+        artifact: &A,
+    ) -> Result<ClientFlow<'_>, APIClientError> {
+        self.collect(A::NAME, &artifact.parameters()).await
+    }
+}
+
+impl ClientFlow<'_> {
+    /// Wait for the results of a built-in artifact collected by the
+    /// flow and return them. Only the rows of the artifact's
+    /// [`SOURCE`](BuiltinArtifact::SOURCE) are read, if it has one.
+    pub async fn fetch_builtin<A: BuiltinArtifact>(&self) -> Result<Vec<A::Row>, APIClientError> {
+        let artifact = results_name::<A>();
+        self.api_client
+            .bounded(
+                &format!("fetching results of flow {}", self.flow_id),
+                self.waiter().artifact_results(&artifact),
+            )
+            .await
+    }
+}
@@ -0,0 +1,164 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use velociraptor_api::{APIClientError, ConfigError};
+
+/// Error that occurred while running a subcommand on a client
+#[derive(Debug)]
+pub struct ClientError {
+    pub client_id: String,
+    pub source: Box<dyn Error>,
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.client_id, self.source)
+    }
+}
+
+impl Error for ClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.source)
+    }
+}
+
+/// Remote command that exited with a non-zero return code
+#[derive(Debug)]
+pub struct RemoteCommandError {
+    pub return_code: i32,
+}
+
+impl std::fmt::Display for RemoteCommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "remote command exited with return code {}",
+            self.return_code
+        )
+    }
+}
+
+impl Error for RemoteCommandError {}
+
+/// Error reading the API client config
+#[derive(Debug)]
+pub struct ConfigFileError {
+    pub path: PathBuf,
+    pub source: ConfigError,
+}
+
+impl std::fmt::Display for ConfigFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "read config: {} {}", self.path.display(), self.source)
+    }
+}
+
+impl Error for ConfigFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// gRPC status of a failed RPC
+#[derive(Serialize)]
+pub struct GrpcStatus {
+    code: i32,
+    name: String,
+    message: String,
+}
+
+/// Structured description of an error, as printed by `--json-errors`
+#[derive(Serialize)]
+pub struct ErrorReport {
+    /// Failure mode, e.g. `connection`, `timeout`, or `flow_failed`
+    kind: &'static str,
+    message: String,
+    client_id: Option<String>,
+    flow_id: Option<String>,
+    grpc_status: Option<GrpcStatus>,
+    /// Return code of a failed remote command
+    return_code: Option<i32>,
+    #[serde(skip)]
+    exit_code: i32,
+}
+
+/// First word in `message` that starts with `prefix`, e.g. a flow ID
+fn find_id(message: &str, prefix: &str) -> Option<String> {
+    message
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '.'))
+        .find(|w| w.len() > prefix.len() && w.starts_with(prefix))
+        .map(|w| w.trim_end_matches('.').to_string())
+}
+
+/// Failure mode and exit code for a gRPC status code
+fn classify_status(status: &tonic::Status) -> (&'static str, i32) {
+    match status.code() {
+        tonic::Code::Unavailable => ("connection", 4),
+        tonic::Code::Unauthenticated | tonic::Code::PermissionDenied => ("permission_denied", 5),
+        tonic::Code::DeadlineExceeded => ("timeout", 6),
+        tonic::Code::NotFound => ("not_found", 7),
+        _ => ("rpc", 8),
+    }
+}
+
+impl ErrorReport {
+    pub fn new(error: &(dyn Error + 'static)) -> Self {
+        let mut report = ErrorReport {
+            kind: "other",
+            message: error.to_string(),
+            client_id: None,
+            flow_id: None,
+            grpc_status: None,
+            return_code: None,
+            exit_code: 1,
+        };
+        let mut cause = Some(error);
+        while let Some(e) = cause {
+            if let Some(e) = e.downcast_ref::<ClientError>() {
+                report.client_id = Some(e.client_id.clone());
+            } else if let Some(e) = e.downcast_ref::<APIClientError>() {
+                (report.kind, report.exit_code) = match e {
+                    APIClientError::HTTP(_) | APIClientError::Transport(_) => ("connection", 4),
+                    APIClientError::Status(status) => {
+                        report.grpc_status = Some(GrpcStatus {
+                            code: status.code() as i32,
+                            name: format!("{:?}", status.code()),
+                            message: status.message().to_string(),
+                        });
+                        classify_status(status)
+                    }
+                    APIClientError::Timeout(_) => ("timeout", 6),
+                    APIClientError::FlowFailed(_) => ("flow_failed", 9),
+                    APIClientError::VQL(_) | APIClientError::MalformedResponse(_) => ("vql", 10),
+                    APIClientError::UnknownArtifact(_)
+                    | APIClientError::MalformedArtifact(_)
+                    | APIClientError::InvalidParameters(..) => ("invalid_input", 11),
+                    APIClientError::IO(_) => ("io", 12),
+                    APIClientError::Integrity(_) => ("integrity", 13),
+                };
+                break;
+            } else if let Some(e) = e.downcast_ref::<RemoteCommandError>() {
+                (report.kind, report.exit_code) = ("remote_command", 14);
+                report.return_code = Some(e.return_code);
+                break;
+            } else if e.is::<ConfigError>() {
+                (report.kind, report.exit_code) = ("config", 3);
+                break;
+            } else if e.is::<std::io::Error>() {
+                (report.kind, report.exit_code) = ("io", 12);
+                break;
+            }
+            cause = e.source();
+        }
+        report.client_id = report.client_id.or_else(|| find_id(&report.message, "C."));
+        report.flow_id = find_id(&report.message, "F.");
+        report
+    }
+
+    /// Exit code for the failure mode
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code
+    }
+}
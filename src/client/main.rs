@@ -1,15 +1,38 @@
-use std::io::Write;
+use std::io::{IsTerminal, Read, Write};
 use std::path::PathBuf;
 
 use clap::Parser;
 
-use velociraptor_api::{APIClient, APIClientConfig, QueryOptions};
+use futures::stream::StreamExt;
+
+use velociraptor_api::builtin::ClientInfo;
+use velociraptor_api::{
+    APIClient, APIClientConfig, APIClientError, ArchiveOptions, ArtifactDefinition, ArtifactRow,
+    ArtifactType, ClientFlow, CollectLimits, EventCursor, ExportFormat, NotebookCell,
+    NotebookFilter, QueryOptions, VfsPath,
+};
 
 use serde::{Deserialize, Serialize};
 
+mod output;
+use output::{print_rows, select_fields, select_row_fields, write_rows, OutputFormat, RowWriter};
+mod errors;
+use errors::{ClientError, ConfigFileError, ErrorReport, RemoteCommandError};
+mod picker;
+mod profiles;
+mod progress;
+mod shell;
+use shell::{Shell, ShellScript};
+
+/// Directory holding API client configs for all profiles
+fn config_dir() -> PathBuf {
+    let mut d = dirs::config_dir().unwrap();
+    d.push("velociraptor");
+    d
+}
+
 fn config_yml_file(i: Option<String>) -> PathBuf {
-    let mut f = dirs::config_dir().unwrap();
-    f.push("velociraptor");
+    let mut f = config_dir();
     if let Some(i) = i {
         f.push(format!("apiclient-{i}.yaml"));
     } else {
@@ -18,6 +41,12 @@ fn config_yml_file(i: Option<String>) -> PathBuf {
     f
 }
 
+/// File holding the default org for the profile whose API client
+/// config is stored in `client_yaml`
+fn default_org_file(client_yaml: &std::path::Path) -> PathBuf {
+    client_yaml.with_extension("org")
+}
+
 #[derive(Parser, Debug, Clone)]
 #[clap(version, about)]
 struct Cli {
@@ -26,6 +55,32 @@ struct Cli {
     config: Option<PathBuf>,
     #[clap(long)]
     instance: Option<String>,
+    /// Org ID to use, overriding the profile's default org
+    #[clap(long, global = true)]
+    org: Option<String>,
+    /// Output format for result rows (json, jsonl, csv, yaml, table)
+    #[clap(long, global = true, default_value = "json")]
+    format: OutputFormat,
+    /// Print one compact JSON object per line, same as --format jsonl
+    #[clap(long, alias = "compact", global = true, conflicts_with = "format")]
+    raw: bool,
+    /// Give up on queries, flow waits, and fetches after this long
+    /// (e.g. 90s, 5m)
+    #[clap(long, global = true, value_parser = parse_duration)]
+    timeout: Option<std::time::Duration>,
+    /// Don't show progress of flows and downloads on the terminal
+    #[clap(long, global = true)]
+    no_progress: bool,
+    /// Log more details (-v: info, -vv: debug, -vvv: trace)
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Print errors as JSON objects, with kind, message, client and
+    /// flow IDs, and gRPC status
+    #[clap(long, global = true)]
+    json_errors: bool,
+    /// Don't log anything, not even errors
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
     #[clap(subcommand)]
     sub: SubCommand,
 }
@@ -38,26 +93,68 @@ enum SubCommand {
     Client(ClientCmd),
     /// Fetch a file from server
     Fetch(FetchCmd),
+    /// Upload a file to the server's filestore
+    Upload(UploadCmd),
+    /// Manage artifacts
+    Artifact(ArtifactCmd),
+    /// Stream event artifact rows as JSONL, or archive them to rotated
+    /// JSONL files
+    Watch(WatchCmd),
+    /// Print server event artifact rows as JSONL
+    Tail(TailCmd),
+    /// Manage notebooks
+    Notebook(NotebookCmd),
+    /// Manage orgs and the profile's default org
+    Org(OrgCmd),
+    /// Manage users and their permissions
+    User(UserCmd),
+    /// Check configuration, certificate, connectivity, and permissions
+    Status,
+    /// List, show, and test the configured profiles
+    Profiles(ProfilesCmd),
 }
 
 #[derive(clap::Args, Clone, Debug)]
 struct QueryCmd {
-    /// Org ID to use
-    #[clap(long)]
-    org: Option<String>,
+    /// Only print these fields, e.g. Name,Pid,Exe.Path
+    #[clap(long, use_value_delimiter = true)]
+    fields: Vec<String>,
     /// Add query environment values in the form of Key=Value
     #[clap(long,value_parser=parse_key_val::<String,String>)]
     env: Vec<(String, String)>,
-    /// The query to run
-    #[clap(value_parser)]
-    query: String,
+    /// Read query environment values from a JSON or YAML file holding
+    /// a single object. Values given with --env take precedence.
+    #[clap(long)]
+    env_file: Option<PathBuf>,
+    /// Read the query from a file, or from stdin if FILE is "-"
+    #[clap(long, short, conflicts_with = "query")]
+    file: Option<PathBuf>,
+    /// The query to run, "-" to read it from stdin
+    #[clap(value_parser, required_unless_present = "file")]
+    query: Option<String>,
 }
 
 #[derive(Parser, Clone, Debug)]
 struct ClientCmd {
-    /// Client ID
-    #[clap(value_parser)]
-    client: String,
+    /// Client ID, partial client ID, or hostname. If several clients
+    /// match, one can be picked interactively.
+    #[clap(
+        value_parser,
+        required_unless_present_any = &["clients", "label", "clients-file"]
+    )]
+    client: Option<String>,
+    /// Additional client ID or hostname; may be given multiple times
+    #[clap(long = "client", value_name = "CLIENT")]
+    clients: Vec<String>,
+    /// Run on all clients with this label
+    #[clap(long)]
+    label: Option<String>,
+    /// Read client IDs from a file, one per line
+    #[clap(long)]
+    clients_file: Option<PathBuf>,
+    /// Number of clients to run on concurrently
+    #[clap(long, default_value_t = 10)]
+    parallel: usize,
     #[clap(subcommand)]
     sub: ClientSubCommand,
 }
@@ -72,25 +169,169 @@ enum ClientSubCommand {
     Cmd(CmdArgs),
     /// Issue a client command using PowerShell
     Powershell(CmdArgs),
+    /// Issue a client command using the shell for the client's OS:
+    /// Bash, or CMD.EXE on Windows
+    Exec(ExecArgs),
+    /// Collect Generic.Client.Info and print a host summary
+    Interrogate,
+    /// List and manage the client's flows
+    Flows(FlowsCmd),
+    /// Collect an artifact
+    Collect(CollectCmd),
+    /// Browse and retrieve files on the client
+    Vfs(VfsCmd),
+}
+
+#[derive(Parser, Clone, Debug)]
+struct VfsCmd {
+    #[clap(subcommand)]
+    sub: VfsSubCommand,
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+enum VfsSubCommand {
+    /// List a directory on the client
+    Ls(VfsPathArgs),
+    /// Retrieve a file from the client
+    Download(VfsDownloadArgs),
+    /// Refresh the server's view of a directory tree on the client
+    Refresh(VfsRefreshArgs),
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct VfsPathArgs {
+    /// Path on the client
+    #[clap(value_parser)]
+    path: String,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct VfsDownloadArgs {
+    /// Path on the client
+    #[clap(value_parser)]
+    remote: String,
+    /// Name of (local) output file
+    #[clap(value_parser)]
+    local: PathBuf,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct VfsRefreshArgs {
+    /// Number of subdirectory levels to refresh
+    #[clap(long, default_value_t = 0)]
+    depth: u32,
+    /// Path on the client
+    #[clap(value_parser)]
+    path: String,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct ExecArgs {
+    /// Use PowerShell instead of CMD.EXE on Windows clients
+    #[clap(long)]
+    powershell: bool,
+    #[clap(flatten)]
+    cmd: CmdArgs,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct CollectCmd {
+    /// Artifact parameter in the form of Key=Value
+    #[clap(long,value_parser=parse_key_val::<String,String>)]
+    param: Vec<(String, String)>,
+    /// Read artifact parameters from a JSON or YAML file holding a
+    /// single object. Values given with --param take precedence.
+    #[clap(long, alias = "env-file")]
+    param_file: Option<PathBuf>,
+    /// Wait for the flow to finish and print its results
+    #[clap(long)]
+    wait: bool,
+    /// Wait for the flow to finish and download uploaded files into
+    /// this directory
+    #[clap(long)]
+    download_uploads: Option<PathBuf>,
+    /// Validate parameters and print the collection that would be
+    /// scheduled, without scheduling it
+    #[clap(long)]
+    dry_run: bool,
+    /// Stop the collection after this many result rows
+    #[clap(long)]
+    max_rows: Option<u64>,
+    /// Stop the collection after uploading this many bytes
+    #[clap(long)]
+    max_bytes: Option<u64>,
+    /// Limit the client's CPU usage to this percentage
+    #[clap(long)]
+    cpu_limit: Option<u32>,
+    /// Cancel the collection on the client after this long (e.g. 10m)
+    #[clap(long, value_parser = parse_duration)]
+    flow_timeout: Option<std::time::Duration>,
+    /// Artifact to collect
+    #[clap(value_parser)]
+    artifact: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+struct FlowsCmd {
+    #[clap(subcommand)]
+    sub: Option<FlowsSubCommand>,
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+enum FlowsSubCommand {
+    /// List flows, most recent first (default)
+    List,
+    /// Show a flow's state and statistics
+    Status(FlowIdArgs),
+    /// Cancel a running flow
+    Cancel(FlowIdArgs),
+    /// Print the results collected by a flow so far
+    Results(FlowResultsArgs),
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct FlowResultsArgs {
+    /// Also download files uploaded by the flow into this directory
+    #[clap(long)]
+    download_uploads: Option<PathBuf>,
+    /// Flow ID
+    #[clap(value_parser)]
+    flow: String,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct FlowIdArgs {
+    /// Flow ID
+    #[clap(value_parser)]
+    flow: String,
 }
 
 #[derive(Clone, Debug, Parser)]
 struct CmdArgs {
+    /// Don't pass stdin to the command, even if it is not a terminal
+    #[clap(long)]
+    no_stdin: bool,
     #[clap(value_parser)]
     command: String,
+    /// Data read from stdin, passed to the command's standard input
+    #[clap(skip)]
+    input: Option<Vec<u8>>,
 }
 
 #[derive(clap::Args, Clone, Debug)]
 struct ClientQueryCmd {
-    /// Org ID to use
-    #[clap(long)]
-    org: Option<String>,
+    /// Only print these fields, e.g. Name,Pid,Exe.Path
+    #[clap(long, use_value_delimiter = true)]
+    fields: Vec<String>,
     /// Add query environment values in the form of Key=Value
     #[clap(long,value_parser=parse_key_val::<String,String>)]
     env: Vec<(String, String)>,
-    /// The query to run
-    #[clap(value_parser)]
-    query: String,
+    /// Read the query from a file, or from stdin if FILE is "-"
+    #[clap(long, short, conflicts_with = "query")]
+    file: Option<PathBuf>,
+    /// The query to run, "-" to read it from stdin
+    #[clap(value_parser, required_unless_present = "file")]
+    query: Option<String>,
 }
 
 #[derive(clap::Args, Clone, Debug)]
@@ -98,12 +339,326 @@ struct FetchCmd {
     #[clap(long)]
     /// Name of (local) output file
     output_file: PathBuf,
+    #[clap(long, default_value_t = 1)]
+    /// Number of parallel range requests
+    parallel: usize,
     #[clap(value_parser)]
     /// Name of (remote) file, usually in the form of
     /// downloads/C.XXXXXXXXXXXXXXXX/F.YYYYYYYYYYYYY/HOSTNAME-C.XXXXXXXXXXXXXXXX-F.YYYYYYYYYYYYY.zip
     path: PathBuf,
 }
 
+#[derive(Parser, Clone, Debug)]
+struct UploadCmd {
+    #[clap(value_parser)]
+    /// Name of (local) input file, or "-" for stdin
+    input_file: PathBuf,
+    #[clap(value_parser)]
+    /// Name of (remote) file, e.g. public/tool.exe. If it ends with
+    /// "/", the input file's name is appended.
+    path: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+struct ArtifactCmd {
+    #[clap(subcommand)]
+    sub: ArtifactSubCommand,
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+enum ArtifactSubCommand {
+    /// List artifacts, optionally filtered by keyword and type
+    List(ArtifactListCmd),
+    /// Show an artifact's YAML definition
+    Show(ArtifactNameArgs),
+    /// Add or replace a custom artifact from a YAML file
+    Push(ArtifactPushArgs),
+    /// Remove a custom artifact
+    Rm(ArtifactNameArgs),
+    /// Verify an artifact YAML file without saving it
+    Verify(ArtifactFileArgs),
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct ArtifactListCmd {
+    /// Artifact type (client, server, client_event, server_event, notebook)
+    #[clap(long = "type")]
+    kind: Option<ArtifactType>,
+    /// Keyword to search for in artifact names and descriptions
+    #[clap(value_parser)]
+    term: Option<String>,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct ArtifactNameArgs {
+    /// Artifact name
+    #[clap(value_parser)]
+    name: String,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct ArtifactFileArgs {
+    /// Artifact YAML file
+    #[clap(value_parser)]
+    file: PathBuf,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct ArtifactPushArgs {
+    /// Verify the artifact and print whether it would be added or
+    /// replaced, without changing anything on the server
+    #[clap(long)]
+    dry_run: bool,
+    /// Artifact YAML file
+    #[clap(value_parser)]
+    file: PathBuf,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct WatchCmd {
+    /// Directory for JSONL files and resume markers. Rows are printed
+    /// to stdout if omitted.
+    #[clap(long)]
+    output: Option<PathBuf>,
+    /// Reconnect when the connection to the server is lost, replaying
+    /// events stored in the meantime
+    #[clap(long, short, conflicts_with = "output")]
+    follow: bool,
+    /// Start a new file after this many bytes
+    #[clap(long, default_value_t = 64 * 1024 * 1024)]
+    max_size: u64,
+    /// Start a new file after this many seconds
+    #[clap(long)]
+    max_age: Option<u64>,
+    /// Event artifact to watch
+    #[clap(value_parser)]
+    artifact: String,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct TailCmd {
+    /// Keep printing new rows as they arrive
+    #[clap(long, short)]
+    follow: bool,
+    /// Print stored rows starting from this time, given as seconds since
+    /// the epoch or relative to now (e.g. 30m, 2h, 1d). Defaults to 1h
+    /// unless --follow is given.
+    #[clap(long, value_parser=parse_since)]
+    since: Option<time::OffsetDateTime>,
+    /// Event artifact to print
+    #[clap(value_parser)]
+    artifact: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+struct NotebookCmd {
+    #[clap(subcommand)]
+    sub: NotebookSubCommand,
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+enum NotebookSubCommand {
+    /// Create a notebook from Markdown (*.md) and VQL (*.vql) files
+    Create(NotebookCreateCmd),
+    /// List notebooks
+    List(NotebookListCmd),
+    /// Export a notebook
+    Export(NotebookExportCmd),
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct NotebookCreateCmd {
+    /// Notebook title
+    #[clap(long)]
+    title: String,
+    /// Cell contents, one file per cell
+    #[clap(value_parser)]
+    cells: Vec<PathBuf>,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct NotebookListCmd {
+    /// Only notebooks created by this user
+    #[clap(long)]
+    creator: Option<String>,
+    /// Only notebooks shared with this user
+    #[clap(long)]
+    shared_with: Option<String>,
+    /// Only notebooks attached to this hunt
+    #[clap(long)]
+    hunt: Option<String>,
+    /// Only notebooks attached to this flow
+    #[clap(long)]
+    flow: Option<String>,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct NotebookExportCmd {
+    /// Export format (html, zip)
+    #[clap(long = "type", default_value = "html")]
+    kind: ExportFormat,
+    /// Name of (local) output file
+    #[clap(long)]
+    output_file: PathBuf,
+    /// Notebook ID
+    #[clap(value_parser)]
+    notebook: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+struct ProfilesCmd {
+    #[clap(subcommand)]
+    sub: ProfilesSubCommand,
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+enum ProfilesSubCommand {
+    /// List profiles in the configuration directory
+    List,
+    /// Show a profile's configuration, with key material redacted
+    Show(ProfileArgs),
+    /// Test connectivity for the given profiles, or for all profiles
+    Test(ProfilesTestArgs),
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct ProfileArgs {
+    /// Instance name; the default profile if omitted
+    #[clap(value_parser)]
+    instance: Option<String>,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct ProfilesTestArgs {
+    /// Instance names; "default" refers to the default profile
+    #[clap(value_parser)]
+    instances: Vec<String>,
+}
+
+#[derive(Parser, Clone, Debug)]
+struct OrgCmd {
+    #[clap(subcommand)]
+    sub: OrgSubCommand,
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+enum OrgSubCommand {
+    /// List orgs
+    List,
+    /// Create an org, printing its ID
+    Create(OrgNameArgs),
+    /// Delete an org and all its data
+    Delete(OrgIdArgs),
+    /// Set the default org for the current profile. Without an org ID,
+    /// the default org is removed.
+    Switch(OrgSwitchArgs),
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct OrgNameArgs {
+    /// Org name
+    #[clap(value_parser)]
+    name: String,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct OrgIdArgs {
+    /// Org ID
+    #[clap(value_parser, value_name = "ORG")]
+    org_id: String,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct OrgSwitchArgs {
+    /// Org ID
+    #[clap(value_parser, value_name = "ORG")]
+    org_id: Option<String>,
+}
+
+#[derive(Parser, Clone, Debug)]
+struct UserCmd {
+    #[clap(subcommand)]
+    sub: UserSubCommand,
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+enum UserSubCommand {
+    /// List users of all orgs
+    List,
+    /// Create a user, or add an existing user to orgs
+    Add(UserAddCmd),
+    /// Replace a user's roles and permissions
+    Grant(UserGrantCmd),
+    /// Remove a user from the current org
+    Rm(UserNameArgs),
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct UserAddCmd {
+    /// Role to assign, e.g. reader, analyst, investigator
+    #[clap(long)]
+    role: Vec<String>,
+    /// Org to add the user to. Defaults to the current org.
+    #[clap(long = "to-org")]
+    to_org: Vec<String>,
+    /// Read the user's password from the first line of stdin
+    #[clap(long)]
+    password_stdin: bool,
+    /// User name
+    #[clap(value_parser)]
+    name: String,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct UserGrantCmd {
+    /// Role to assign
+    #[clap(long)]
+    role: Vec<String>,
+    /// Additional permission to grant, e.g. COLLECT_CLIENT
+    #[clap(long)]
+    permission: Vec<String>,
+    /// User name
+    #[clap(value_parser)]
+    name: String,
+}
+
+#[derive(clap::Args, Clone, Debug)]
+struct UserNameArgs {
+    /// User name
+    #[clap(value_parser)]
+    name: String,
+}
+
+/// Parse an absolute (epoch seconds) or relative (30m, 2h, 1d) time
+fn parse_since(s: &str) -> Result<time::OffsetDateTime, String> {
+    if let Ok(ts) = s.parse::<i64>() {
+        return time::OffsetDateTime::from_unix_timestamp(ts).map_err(|e| e.to_string());
+    }
+    let seconds = parse_seconds(s)?;
+    Ok(time::OffsetDateTime::now_utc() - time::Duration::seconds(seconds as i64))
+}
+
+/// Parse a duration given in seconds or with a unit (90s, 5m, 1h)
+fn parse_duration(s: &str) -> Result<std::time::Duration, String> {
+    match s.parse::<u64>() {
+        Ok(seconds) => Ok(std::time::Duration::from_secs(seconds)),
+        Err(_) => parse_seconds(s).map(std::time::Duration::from_secs),
+    }
+}
+
+/// Parse a number of seconds, minutes, hours, or days (30s, 30m, 2h, 1d)
+fn parse_seconds(s: &str) -> Result<u64, String> {
+    let (num, unit) = s.split_at(s.len() - s.trim_start_matches(char::is_numeric).len());
+    let num: u64 = num.parse().map_err(|_| format!("invalid time: {s}"))?;
+    match unit {
+        "s" => Ok(num),
+        "m" => Ok(num * 60),
+        "h" => Ok(num * 3600),
+        "d" => Ok(num * 86400),
+        _ => Err(format!("invalid time unit in {s}")),
+    }
+}
+
 /// Parse a single key-value pair
 fn parse_key_val<T, U>(
     s: &str,
@@ -120,160 +675,1056 @@ where
     Ok((s[..pos].parse()?, s[pos + 1..].parse()?))
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// Key-value pairs read from a JSON or YAML file, followed by those
+/// given on the command line, which take precedence. Values that are
+/// not strings are passed as JSON.
+fn merge_env_file(
+    file: &Option<PathBuf>,
+    values: &[(String, String)],
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let Some(file) = file else {
+        return Ok(values.to_vec());
+    };
+    let content = std::fs::read_to_string(file)
+        .map_err(|e| format!("read environment: {}: {e}", file.display()))?;
+    let map: serde_json::Map<String, serde_json::Value> = serde_yaml::from_str(&content)
+        .map_err(|e| format!("parse environment: {}: {e}", file.display()))?;
+    let mut merged: Vec<(String, String)> = map
+        .into_iter()
+        .filter(|(k, _)| !values.iter().any(|(key, _)| key == k))
+        .map(|(k, v)| match v {
+            serde_json::Value::String(s) => (k, s),
+            v => (k, v.to_string()),
+        })
+        .collect();
+    merged.extend_from_slice(values);
+    Ok(merged)
+}
+
+#[derive(Debug, Default, ArtifactRow)]
 struct ShellResult {
-    #[serde(rename = "Stdout")]
     stdout: String,
-    #[serde(rename = "Stderr")]
     stderr: String,
-    #[serde(rename = "ReturnCode")]
-    returncode: i32,
-    #[serde(rename = "Complete")]
+    return_code: i32,
+    #[artifact(rename = "Complete")]
     finished: bool,
 }
 
 impl ShellResult {
-    fn do_output(&self) -> Result<(), Box<dyn std::error::Error>> {
-        write!(std::io::stdout(), "{}", self.stdout)?;
-        write!(std::io::stderr(), "{}", self.stderr)?;
-        Ok(())
+    /// Combine the chunks of output of a single command. The return
+    /// code is only reported in the final chunk.
+    fn combine(rows: Vec<ShellResult>) -> ShellResult {
+        rows.into_iter()
+            .fold(ShellResult::default(), |acc, item| ShellResult {
+                stdout: acc.stdout + &item.stdout,
+                stderr: acc.stderr + &item.stderr,
+                return_code: match item.finished || item.return_code != 0 {
+                    true => item.return_code,
+                    false => acc.return_code,
+                },
+                finished: acc.finished || item.finished,
+            })
+    }
+
+    /// Write output, returning the command's return code
+    fn do_output(
+        &self,
+        out: &mut dyn Write,
+        err: &mut dyn Write,
+    ) -> Result<i32, Box<dyn std::error::Error>> {
+        write!(out, "{}", self.stdout)?;
+        write!(err, "{}", self.stderr)?;
+        out.flush()?;
+        Ok(self.return_code)
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+/// Concise host summary printed by `client <id> interrogate`
+#[derive(Serialize)]
+struct HostSummary {
+    client_id: String,
+    hostname: String,
+    fqdn: String,
+    os: String,
+    /// Address the server last saw the client connect from, which
+    /// may be that of a NAT gateway or proxy
+    last_seen_ip: String,
+    agent_version: String,
+}
 
-    env_logger::init();
+/// Read a query given on the command line, from a file, or from
+/// stdin if either is "-"
+fn read_query(
+    query: &Option<String>,
+    file: &Option<PathBuf>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let from_stdin = || -> std::io::Result<String> {
+        let mut query = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut query)?;
+        Ok(query)
+    };
+    match (query.as_deref(), file) {
+        (Some("-"), _) => Ok(from_stdin()?),
+        (Some(query), _) => Ok(query.to_string()),
+        (None, Some(file)) if file.as_os_str() == "-" => Ok(from_stdin()?),
+        (None, Some(file)) => Ok(std::fs::read_to_string(file)
+            .map_err(|e| format!("read query: {}: {e}", file.display()))?),
+        (None, None) => Err("no query given".into()),
+    }
+}
 
-    let client_yaml: PathBuf = match (cli.config, cli.instance) {
-        (Some(c), None) => c,
-        (None, x) => config_yml_file(x),
-        _ => return Err("can't use config and instance simultaneously".into()),
+/// Apply the profile's default org, unless overridden by `org`
+fn apply_org(api_client: APIClient, client_yaml: &std::path::Path, org: Option<&str>) -> APIClient {
+    let default_org = std::fs::read_to_string(default_org_file(client_yaml)).ok();
+    match org.or(default_org.as_deref().map(str::trim)) {
+        Some(org) if !org.is_empty() => api_client.with_org(org),
+        _ => api_client,
+    }
+}
+
+/// Collection that would be scheduled by `collect --dry-run`
+#[derive(Serialize)]
+struct PlannedCollection<'a> {
+    client_id: &'a str,
+    artifact: &'a str,
+    params: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Result of a single `status` check
+#[derive(Serialize)]
+struct StatusCheck {
+    check: &'static str,
+    ok: bool,
+    detail: String,
+}
+
+/// Certificates expiring within this many days are reported
+const CERT_EXPIRY_WARN_DAYS: i64 = 30;
+
+async fn status(
+    client_yaml: &std::path::Path,
+    org: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut checks = vec![];
+    let mut check = |check, result: Result<String, String>| {
+        let ok = result.is_ok();
+        let detail = result.unwrap_or_else(|e| e);
+        checks.push(StatusCheck { check, ok, detail });
+        ok
     };
 
-    let api_client = APIClient::try_from(
-        &APIClientConfig::from_yaml_file(&client_yaml)
-            .map_err(|e| format!("read config: {} {e}", client_yaml.to_string_lossy()))?,
-    )?;
+    let config = APIClientConfig::from_yaml_file(&client_yaml);
+    check(
+        "config",
+        config
+            .as_ref()
+            .map(|c| format!("{} ({})", client_yaml.display(), c.name()))
+            .map_err(|e| format!("{}: {e}", client_yaml.display())),
+    );
+    if let Ok(config) = &config {
+        check(
+            "certificate",
+            config
+                .certificate_expiry()
+                .map_err(|e| e.to_string())
+                .and_then(|expiry| {
+                    let days = (expiry - time::OffsetDateTime::now_utc()).whole_days();
+                    match days {
+                        d if d < 0 => Err(format!("expired at {expiry}")),
+                        d if d < CERT_EXPIRY_WARN_DAYS => {
+                            Ok(format!("expires in {d} days, at {expiry}"))
+                        }
+                        _ => Ok(format!("valid until {expiry}")),
+                    }
+                }),
+        );
+        match APIClient::try_from(config) {
+            Err(e) => {
+                check("connectivity", Err(e.to_string()));
+            }
+            Ok(api_client) => {
+                let api_client = apply_org(api_client, client_yaml, org);
+                let connected = check(
+                    "connectivity",
+                    api_client
+                        .server_info()
+                        .await
+                        .map(|info| {
+                            format!(
+                                "{} {} ({}, built {})",
+                                config.api_connection_string(),
+                                info.version,
+                                info.commit,
+                                info.build_time
+                            )
+                        })
+                        .map_err(|e| format!("{}: {e}", config.api_connection_string())),
+                );
+                if connected {
+                    check(
+                        "permissions",
+                        api_client
+                            .whoami()
+                            .await
+                            .map(|me| {
+                                format!(
+                                    "{} in org {}: roles {}; permissions {}",
+                                    me.name,
+                                    api_client.org_id().unwrap_or("root"),
+                                    me.permissions.roles.join(", "),
+                                    me.permissions.permissions.join(", ")
+                                )
+                            })
+                            .map_err(|e| e.to_string()),
+                    );
+                }
+            }
+        }
+    }
 
-    match cli.sub {
-        SubCommand::Query(ref cmd) => {
-            let result = api_client
-                .sync_query::<serde_json::Value>(
-                    &cmd.query,
+    let ok = checks.iter().all(|c| c.ok);
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&serde_json::json!({ "ok": ok, "checks": checks }))?
+    );
+    if !ok {
+        return Err("status checks failed".into());
+    }
+    Ok(())
+}
+
+async fn run_profiles(
+    cmd: &ProfilesCmd,
+    format: OutputFormat,
+    timeout: Option<std::time::Duration>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let all = profiles::profiles(&config_dir())?;
+    let find = |instance: Option<&str>| {
+        let instance = instance.filter(|i| *i != "default");
+        all.iter()
+            .find(|p| p.instance.as_deref() == instance)
+            .ok_or_else(|| format!("unknown profile: {}", instance.unwrap_or("default")))
+    };
+    match &cmd.sub {
+        ProfilesSubCommand::List => {
+            let rows: Vec<_> = all.iter().map(profiles::summary).collect();
+            print_rows(format, &rows)?;
+        }
+        ProfilesSubCommand::Show(args) => {
+            let details = profiles::details(find(args.instance.as_deref())?)?;
+            print_rows(format, &[details])?;
+        }
+        ProfilesSubCommand::Test(args) => {
+            let selected = match args.instances.as_slice() {
+                [] => all.iter().collect(),
+                instances => instances
+                    .iter()
+                    .map(|i| find(Some(i)))
+                    .collect::<Result<Vec<_>, _>>()?,
+            };
+            let results =
+                futures::future::join_all(selected.into_iter().map(|p| profiles::test(p, timeout)))
+                    .await;
+            print_rows(format, &results)?;
+            if !results.iter().all(|r| r.ok) {
+                return Err("profile tests failed".into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run a client subcommand against a single client, writing output to
+/// `out` and `err`. Returns the exit code.
+async fn run_client(
+    api_client: &APIClient,
+    client_id: &str,
+    sub: &ClientSubCommand,
+    format: OutputFormat,
+    show_progress: bool,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    match sub {
+        ClientSubCommand::Collect(cmd) if cmd.dry_run => {
+            let params = merge_env_file(&cmd.param_file, &cmd.param)?;
+            api_client
+                .artifact(&cmd.artifact)
+                .await?
+                .validate_parameters(&params)
+                .map_err(|e| APIClientError::InvalidParameters(cmd.artifact.clone(), e))?;
+            let plan = PlannedCollection {
+                client_id,
+                artifact: &cmd.artifact,
+                params: params
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone().into()))
+                    .collect(),
+            };
+            write_rows(out, format, &[plan])?;
+        }
+        ClientSubCommand::Collect(cmd) => {
+            let client = api_client.new_client_unchecked(client_id);
+            let params = merge_env_file(&cmd.param_file, &cmd.param)?;
+            let limits = CollectLimits {
+                max_rows: cmd.max_rows,
+                max_bytes: cmd.max_bytes,
+                cpu_limit: cmd.cpu_limit,
+                timeout: cmd.flow_timeout,
+            };
+            let flow = client
+                .collect_with_limits(&cmd.artifact, &params, &limits)
+                .await?;
+            log::debug!("Flow ID: {flow}");
+            if !cmd.wait && cmd.download_uploads.is_none() {
+                writeln!(out, "{flow}")?;
+                return Ok(0);
+            }
+            let spinner = progress::flow_spinner(show_progress, &flow.to_string());
+            flow.wait_with_progress(|summary| progress::update_flow(&spinner, summary))
+                .await?;
+            spinner.finish_and_clear();
+            let result: Vec<serde_json::Value> = flow.results().await?;
+            write_rows(out, format, &result)?;
+            if let Some(ref dir) = cmd.download_uploads {
+                download_uploads(&flow, client_id, dir).await?;
+            }
+        }
+        ClientSubCommand::Vfs(VfsCmd { sub }) => {
+            let client = api_client.new_client_unchecked(client_id);
+            match sub {
+                VfsSubCommand::Ls(args) => {
+                    write_rows(out, format, &client.vfs_ls(&args.path).await?)?;
+                }
+                VfsSubCommand::Download(args) => {
+                    let mut output = tokio::fs::File::create(&args.local).await?;
+                    let written = client.vfs_download(&args.remote, &mut output).await?;
+                    log::info!("Downloaded {written} bytes to {}", args.local.display());
+                }
+                VfsSubCommand::Refresh(args) => {
+                    client.vfs_refresh(&args.path, args.depth).await?;
+                }
+            }
+        }
+        ClientSubCommand::Flows(FlowsCmd { sub }) => {
+            let client = api_client.new_client_unchecked(client_id);
+            match sub {
+                None | Some(FlowsSubCommand::List) => {
+                    write_rows(out, format, &client.flows().await?)?;
+                }
+                Some(FlowsSubCommand::Status(cmd)) => {
+                    let summary = client
+                        .flow_unchecked(&cmd.flow)
+                        .summary()
+                        .await?
+                        .ok_or_else(|| format!("unknown flow: {}", cmd.flow))?;
+                    write_rows(out, format, &[summary])?;
+                }
+                Some(FlowsSubCommand::Cancel(cmd)) => {
+                    client.flow_unchecked(&cmd.flow).cancel().await?;
+                }
+                Some(FlowsSubCommand::Results(cmd)) => {
+                    let flow = client.flow_unchecked(&cmd.flow);
+                    let result: Vec<serde_json::Value> = flow.results().await?;
+                    write_rows(out, format, &result)?;
+                    if let Some(ref dir) = cmd.download_uploads {
+                        download_uploads(&flow, client_id, dir).await?;
+                    }
+                }
+            }
+        }
+        ClientSubCommand::Interrogate => {
+            #[derive(Deserialize)]
+            struct LastIp {
+                last_ip: String,
+            }
+            let client = api_client.new_client_unchecked(client_id);
+            let flow = client.collect_builtin(&ClientInfo).await?;
+            log::debug!("Flow ID: {flow}");
+            let spinner = progress::flow_spinner(show_progress, &flow.to_string());
+            flow.wait_with_progress(|summary| progress::update_flow(&spinner, summary))
+                .await?;
+            spinner.finish_and_clear();
+            let info = flow
+                .fetch_builtin::<ClientInfo>()
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| format!("Flow {flow} returned no results."))?;
+            let ip = api_client
+                .sync_query::<LastIp>(
+                    r#"SELECT last_ip FROM clients(client_id=client_id)"#,
                     &QueryOptions::builder()
-                        .env(cmd.env.clone())
-                        .org_id(cmd.org.clone())
+                        .env(vec![("client_id".into(), client_id.to_string())])
                         .build(),
                 )
-                .await?;
-            println!("{}", serde_json::to_string_pretty(&result)?);
+                .await?
+                .into_iter()
+                .next()
+                .map(|r| r.last_ip)
+                .unwrap_or_default();
+            let summary = HostSummary {
+                client_id: client_id.to_string(),
+                hostname: info.hostname,
+                fqdn: info.fqdn,
+                os: format!(
+                    "{} {} {} ({})",
+                    info.os, info.platform, info.platform_version, info.architecture
+                ),
+                last_seen_ip: ip
+                    .rsplit_once(':')
+                    .map_or(ip.as_str(), |(host, _)| host)
+                    .to_string(),
+                agent_version: info.version,
+            };
+            write_rows(out, format, &[summary])?;
         }
-        SubCommand::Client(ClientCmd {
-            client: client_id,
-            sub: ClientSubCommand::Query(ref cmd),
-        }) => {
-            let client = api_client.new_client_unchecked(&client_id);
+        ClientSubCommand::Query(cmd) => {
+            let client = api_client.new_client_unchecked(client_id);
             let flow = client
-                .schedule_flow("Generic.Client.VQL", &cmd.query)
+                .schedule_flow("Generic.Client.VQL", &read_query(&cmd.query, &cmd.file)?)
                 .await?;
             log::debug!("Flow ID: {flow}");
-            // FIXME: Use select?
-            // FIXME: Use SELECT state FROM flows()?
-            let log = flow.fetch_log().await?;
-            let mut err = false;
+            let mut failed = match flow.wait().await {
+                Ok(()) => false,
+                Err(APIClientError::FlowFailed(_)) => true,
+                Err(e) => return Err(e.into()),
+            };
+            let log = flow.log().await?;
             for entry in log {
-                let timestamp =
-                    time::OffsetDateTime::from_unix_timestamp(entry.client_time as _).unwrap();
+                let timestamp = entry.client_time;
                 log::debug!("log: {timestamp} {}: {}", entry.level, entry.message);
                 if entry.level == "ERROR" || entry.level == "WARN" {
-                    writeln!(
-                        std::io::stderr(),
-                        "{timestamp} {}: {}",
-                        entry.level,
-                        entry.message
-                    )?;
+                    writeln!(err, "{timestamp} {}: {}", entry.level, entry.message)?;
                 }
                 if entry.level == "ERROR" {
-                    err = true;
+                    failed = true;
                 }
             }
-            if err {
+            if failed {
                 return Err(format!("Flow {flow} failed.").into());
             }
             let result: Vec<serde_json::Value> = flow.fetch().await?;
-            println!("{}", serde_json::to_string_pretty(&result)?);
+            write_rows(out, format, &select_fields(result, &cmd.fields))?;
         }
-        SubCommand::Client(ClientCmd {
-            client: client_id,
-            sub: ClientSubCommand::Cmd(ref cmd),
-        }) => {
-            let client = api_client.new_client_unchecked(&client_id);
-            let flow = client
-                .schedule_flow("Windows.System.CmdShell", &cmd.command)
-                .await?;
-            log::debug!("Flow ID: {flow}");
-            flow.fetch()
+        ClientSubCommand::Cmd(cmd) => {
+            return run_shell(api_client, client_id, Shell::Cmd, cmd, out, err).await;
+        }
+        ClientSubCommand::Bash(cmd) => {
+            return run_shell(api_client, client_id, Shell::Bash, cmd, out, err).await;
+        }
+        ClientSubCommand::Exec(args) => {
+            let summary = api_client
+                .new_client_unchecked(client_id)
+                .summary()
                 .await?
-                .into_iter()
-                .fold::<ShellResult, _>(ShellResult::default(), |acc, item: ShellResult| {
-                    ShellResult {
-                        stdout: acc.stdout + &item.stdout,
-                        stderr: acc.stderr + &item.stderr,
-                        ..ShellResult::default()
+                .ok_or_else(|| format!("unknown client: {client_id}"))?;
+            let shell = match (summary.os.as_str(), args.powershell) {
+                ("windows", false) => Shell::Cmd,
+                ("windows", true) => Shell::Powershell,
+                ("linux" | "darwin" | "freebsd", _) => Shell::Bash,
+                (os, _) => return Err(format!("{client_id}: no shell known for OS {os:?}").into()),
+            };
+            log::debug!(
+                "{client_id} runs {}, using {}",
+                summary.os,
+                shell.artifact()
+            );
+            return run_shell(api_client, client_id, shell, &args.cmd, out, err).await;
+        }
+        ClientSubCommand::Powershell(cmd) => {
+            return run_shell(api_client, client_id, Shell::Powershell, cmd, out, err).await;
+        }
+    }
+    Ok(0)
+}
+
+/// Entry in the manifest written after downloading a flow's uploads
+#[derive(Serialize)]
+struct ManifestEntry {
+    /// Path relative to the download directory
+    path: PathBuf,
+    size: u64,
+    sha256: String,
+}
+
+/// Download all files uploaded by a flow into `dir` and write a
+/// manifest with their sizes and SHA-256 hashes to
+/// `CLIENT_ID-FLOW_ID.manifest.json` in the same directory
+async fn download_uploads(
+    flow: &ClientFlow<'_>,
+    client_id: &str,
+    dir: &std::path::Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use sha2::Digest;
+    let mut manifest = vec![];
+    for file in flow.download_uploads(dir, 4).await? {
+        log::info!("Downloaded {}", file.display());
+        let mut hasher = sha2::Sha256::new();
+        let size = std::io::copy(&mut std::fs::File::open(&file)?, &mut hasher)?;
+        manifest.push(ManifestEntry {
+            path: file.strip_prefix(dir).unwrap_or(&file).to_path_buf(),
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+        });
+    }
+    manifest.sort_by(|a, b| a.path.cmp(&b.path));
+    std::fs::create_dir_all(dir)?;
+    let manifest_file = dir.join(format!("{client_id}-{flow}.manifest.json"));
+    std::fs::write(&manifest_file, serde_json::to_string_pretty(&manifest)?)?;
+    log::info!("Wrote manifest {}", manifest_file.display());
+    Ok(())
+}
+
+/// Run a shell command on a client, passing input read from stdin
+/// through temporary files if there is any. Returns the command's
+/// return code.
+async fn run_shell(
+    api_client: &APIClient,
+    client_id: &str,
+    shell: Shell,
+    cmd: &CmdArgs,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let client = api_client.new_client_unchecked(client_id);
+    let script = match cmd.input {
+        Some(ref input) => shell.with_input(&cmd.command, input),
+        None => ShellScript {
+            prelude: vec![],
+            main: cmd.command.clone(),
+            cleanup: None,
+        },
+    };
+    for command in &script.prelude {
+        let flow = client.schedule_flow(shell.artifact(), command).await?;
+        log::debug!("Flow ID: {flow} (input transfer)");
+        let result = ShellResult::combine(flow.fetch().await?);
+        if result.return_code != 0 {
+            return Err(format!(
+                "Flow {flow}: passing input failed: {}",
+                result.stderr.trim()
+            )
+            .into());
+        }
+    }
+    let flow = client.schedule_flow(shell.artifact(), &script.main).await?;
+    log::debug!("Flow ID: {flow}");
+    let result = ShellResult::combine(flow.fetch().await?);
+    if let Some(ref cleanup) = script.cleanup {
+        let flow = client.schedule_flow(shell.artifact(), cleanup).await?;
+        log::debug!("Flow ID: {flow} (cleanup)");
+        flow.fetch::<ShellResult>().await?;
+    }
+    result.do_output(out, err)
+}
+
+/// Run a client subcommand against several clients concurrently.
+/// Output lines are prefixed with the client ID; failures are
+/// summarized at the end.
+async fn run_clients(
+    api_client: &APIClient,
+    client_ids: &[String],
+    cmd: &ClientCmd,
+    format: OutputFormat,
+    json_errors: bool,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let mut runs = futures::stream::iter(client_ids)
+        .map(|client_id| async move {
+            let (mut out, mut err) = (vec![], vec![]);
+            let result = run_client(
+                api_client, client_id, &cmd.sub, format, false, &mut out, &mut err,
+            )
+            .await
+            .map_err(|source| ClientError {
+                client_id: client_id.clone(),
+                source,
+            });
+            (client_id, out, err, result)
+        })
+        .buffer_unordered(cmd.parallel.max(1));
+    let mut failures = vec![];
+    while let Some((client_id, out, err, result)) = runs.next().await {
+        for line in String::from_utf8_lossy(&out).lines() {
+            println!("{client_id}: {line}");
+        }
+        for line in String::from_utf8_lossy(&err).lines() {
+            eprintln!("{client_id}: {line}");
+        }
+        match result {
+            Ok(0) => {}
+            Ok(code) => failures.push(format!("{client_id}: exit code {code}")),
+            Err(e) if json_errors => {
+                eprintln!("{}", serde_json::to_string(&ErrorReport::new(&e))?);
+                failures.push(e.to_string());
+            }
+            Err(e) => failures.push(e.to_string()),
+        }
+    }
+    if failures.is_empty() {
+        return Ok(0);
+    }
+    eprintln!("{} of {} clients failed:", failures.len(), client_ids.len());
+    for failure in &failures {
+        eprintln!("  {failure}");
+    }
+    Ok(1)
+}
+
+/// Client IDs selected on the command line, by label, or from a file,
+/// without duplicates
+async fn resolve_clients(
+    api_client: &APIClient,
+    cmd: &ClientCmd,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    #[derive(Deserialize)]
+    struct Row {
+        client_id: String,
+    }
+    let mut ids = vec![];
+    for term in cmd.client.iter().chain(&cmd.clients) {
+        ids.push(picker::resolve_client(api_client, term).await?);
+    }
+    if let Some(ref file) = cmd.clients_file {
+        let content = std::fs::read_to_string(file)
+            .map_err(|e| format!("read client IDs: {}: {e}", file.display()))?;
+        ids.extend(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(String::from),
+        );
+    }
+    if let Some(ref label) = cmd.label {
+        let rows = api_client
+            .sync_query::<Row>(
+                r#"SELECT client_id FROM clients(search="label:" + label)"#,
+                &QueryOptions::builder()
+                    .env(vec![("label".into(), label.clone())])
+                    .build(),
+            )
+            .await?;
+        if rows.is_empty() {
+            return Err(format!("no clients with label {label}").into());
+        }
+        ids.extend(rows.into_iter().map(|r| r.client_id));
+    }
+    let mut seen = std::collections::HashSet::new();
+    ids.retain(|id| seen.insert(id.clone()));
+    Ok(ids)
+}
+
+/// Configure logging for the library and this tool according to the
+/// verbosity flags. `RUST_LOG` can still be used to override this.
+fn init_logger(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => log::LevelFilter::Off,
+        (false, 0) => log::LevelFilter::Warn,
+        (false, 1) => log::LevelFilter::Info,
+        (false, 2) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(if quiet {
+            log::LevelFilter::Off
+        } else {
+            log::LevelFilter::Error
+        })
+        .filter_module("velociraptor_api", level)
+        .filter_module(module_path!(), level)
+        .parse_default_env()
+        .init();
+}
+
+#[tokio::main]
+async fn main() {
+    let mut cli = Cli::parse();
+    if cli.raw {
+        cli.format = OutputFormat::Jsonl;
+    }
+
+    init_logger(cli.verbose, cli.quiet);
+
+    let json_errors = cli.json_errors;
+    if let Err(e) = run(cli).await {
+        let report = ErrorReport::new(&*e);
+        if json_errors {
+            eprintln!("{}", serde_json::to_string(&report).unwrap());
+        } else {
+            eprintln!("Error: {e}");
+        }
+        std::process::exit(report.exit_code());
+    }
+}
+
+async fn run(mut cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    if let SubCommand::Client(ClientCmd {
+        sub:
+            ClientSubCommand::Bash(ref mut cmd)
+            | ClientSubCommand::Cmd(ref mut cmd)
+            | ClientSubCommand::Powershell(ref mut cmd)
+            | ClientSubCommand::Exec(ExecArgs { ref mut cmd, .. }),
+        ..
+    }) = cli.sub
+    {
+        if !cmd.no_stdin && !std::io::stdin().is_terminal() {
+            let mut input = vec![];
+            std::io::stdin().read_to_end(&mut input)?;
+            cmd.input = Some(input).filter(|i| !i.is_empty());
+        }
+    }
+
+    let client_yaml: PathBuf = match (cli.config, cli.instance) {
+        (Some(c), None) => c,
+        (None, x) => config_yml_file(x),
+        _ => return Err("can't use config and instance simultaneously".into()),
+    };
+
+    if let SubCommand::Status = cli.sub {
+        return status(&client_yaml, cli.org.as_deref()).await;
+    }
+    if let SubCommand::Profiles(ref cmd) = cli.sub {
+        return run_profiles(cmd, cli.format, cli.timeout).await;
+    }
+
+    let api_client = APIClient::try_from(&APIClientConfig::from_yaml_file(&client_yaml).map_err(
+        |source| ConfigFileError {
+            path: client_yaml.clone(),
+            source,
+        },
+    )?)?;
+    let api_client = apply_org(api_client, &client_yaml, cli.org.as_deref());
+    let api_client = match cli.timeout {
+        Some(timeout) => api_client.with_timeout(timeout),
+        None => api_client,
+    };
+
+    match cli.sub {
+        SubCommand::Query(ref cmd) => {
+            let rows = api_client
+                .query_stream::<serde_json::Value>(
+                    &read_query(&cmd.query, &cmd.file)?,
+                    &QueryOptions::builder()
+                        .env(merge_env_file(&cmd.env_file, &cmd.env)?)
+                        .build(),
+                )
+                .await?;
+            let mut rows = std::pin::pin!(rows);
+            let mut stdout = std::io::stdout();
+            let mut writer = RowWriter::new(&mut stdout, cli.format);
+            while let Some(row) = rows.next().await {
+                writer.write(select_row_fields(&row?, &cmd.fields))?;
+            }
+            writer.finish()?;
+        }
+        SubCommand::Client(ref cmd) => {
+            let client_ids = resolve_clients(&api_client, cmd).await?;
+            match client_ids.as_slice() {
+                [client_id] => {
+                    run_client(
+                        &api_client,
+                        client_id,
+                        &cmd.sub,
+                        cli.format,
+                        !cli.no_progress,
+                        &mut std::io::stdout(),
+                        &mut std::io::stderr(),
+                    )
+                    .await
+                    .and_then(|code| match code {
+                        0 => Ok(()),
+                        _ => Err(RemoteCommandError { return_code: code }.into()),
+                    })
+                    .map_err(|source| ClientError {
+                        client_id: client_id.clone(),
+                        source,
+                    })?;
+                }
+                _ => {
+                    let code =
+                        run_clients(&api_client, &client_ids, cmd, cli.format, cli.json_errors)
+                            .await?;
+                    if code != 0 {
+                        std::process::exit(code);
                     }
-                })
-                .do_output()?;
+                }
+            }
+        }
+        SubCommand::Fetch(ref cmd) => {
+            let mut output = tokio::fs::File::create(&cmd.output_file).await?;
+            let bar = progress::fetch_bar(!cli.no_progress);
+            let progress = |done, total: Option<u64>| {
+                log::trace!("fetched {done}/{} bytes", total.unwrap_or_default());
+                progress::update_fetch(&bar, done, total);
+            };
+            if cmd.parallel > 1 {
+                api_client
+                    .fetch_parallel_to(&cmd.path, &mut output, cmd.parallel, progress)
+                    .await?;
+            } else if cli.no_progress {
+                api_client.fetch_to(&cmd.path, &mut output).await?;
+            } else {
+                api_client
+                    .fetch_to_with_progress(&cmd.path, &mut output, progress)
+                    .await?;
+            }
+            bar.finish_and_clear();
+        }
+        SubCommand::Upload(ref cmd) => {
+            let mut path = VfsPath::from(cmd.path.as_str());
+            if cmd.path.ends_with('/') {
+                let name = cmd
+                    .input_file
+                    .file_name()
+                    .ok_or("can't derive remote file name from input file")?;
+                path = path.join(name.to_string_lossy());
+            }
+            let written = if cmd.input_file.as_os_str() == "-" {
+                api_client.upload(&path, &mut tokio::io::stdin()).await?
+            } else {
+                let mut input = tokio::fs::File::open(&cmd.input_file).await?;
+                api_client.upload(&path, &mut input).await?
+            };
+            log::info!("Uploaded {written} bytes to {path}");
         }
-        SubCommand::Client(ClientCmd {
-            client: client_id,
-            sub: ClientSubCommand::Bash(ref cmd),
+        SubCommand::Watch(WatchCmd {
+            output: Some(ref output),
+            ref artifact,
+            max_size,
+            max_age,
+            ..
         }) => {
-            let client = api_client.new_client_unchecked(&client_id);
-            let flow = client
-                .schedule_flow("Linux.Sys.BashShell", &cmd.command)
-                .await?;
-            log::debug!("Flow ID: {flow}");
-            flow.fetch()
-                .await?
-                .into_iter()
-                .fold::<ShellResult, _>(ShellResult::default(), |acc, item: ShellResult| {
-                    ShellResult {
-                        stdout: acc.stdout + &item.stdout,
-                        stderr: acc.stderr + &item.stderr,
-                        ..ShellResult::default()
+            let options = ArchiveOptions::builder()
+                .dir(output)
+                .max_size(max_size)
+                .max_age(max_age.map(std::time::Duration::from_secs))
+                .build();
+            api_client.archive_events(artifact, options).await?;
+        }
+        SubCommand::Watch(ref cmd) => {
+            if !matches!(cli.format, OutputFormat::Json | OutputFormat::Jsonl) {
+                return Err("watch only supports --format jsonl".into());
+            }
+            let mut stdout = std::io::stdout();
+            let mut print = |row: serde_json::Value| -> std::io::Result<()> {
+                writeln!(stdout, "{row}")?;
+                stdout.flush()
+            };
+            if cmd.follow {
+                let rows = api_client.follow_client_events(&cmd.artifact, None);
+                let mut rows = std::pin::pin!(rows);
+                while let Some(row) = rows.next().await {
+                    print(row?)?;
+                }
+            } else {
+                let rows = api_client.watch_events(&cmd.artifact, None).await?;
+                let mut rows = std::pin::pin!(rows);
+                while let Some(row) = rows.next().await {
+                    print(row?)?;
+                }
+            }
+        }
+        SubCommand::Tail(ref cmd) => {
+            let since = match (cmd.since, cmd.follow) {
+                (Some(since), _) => Some(since),
+                (None, false) => Some(time::OffsetDateTime::now_utc() - time::Duration::hours(1)),
+                (None, true) => None,
+            };
+            if cmd.follow {
+                // Stored rows are replayed after subscribing, so that
+                // none are missed in between
+                let cursor = since
+                    .map(|t| EventCursor::starting_at(t.unix_timestamp() as f64))
+                    .unwrap_or_default();
+                let rows = api_client.follow_server_events(&cmd.artifact, cursor);
+                let mut rows = std::pin::pin!(rows);
+                while let Some(row) = rows.next().await {
+                    println!("{}", row?);
+                }
+            } else if let Some(since) = since {
+                let rows = api_client.events_between::<serde_json::Value>(
+                    &cmd.artifact,
+                    since,
+                    time::OffsetDateTime::now_utc(),
+                );
+                let mut rows = std::pin::pin!(rows);
+                while let Some(row) = rows.next().await {
+                    println!("{}", row?);
+                }
+            }
+        }
+        SubCommand::Org(OrgCmd {
+            sub: OrgSubCommand::List,
+        }) => {
+            let result = api_client.orgs().await?;
+            print_rows(cli.format, &result)?;
+        }
+        SubCommand::Org(OrgCmd {
+            sub: OrgSubCommand::Create(ref cmd),
+        }) => {
+            let org = api_client.create_org(&cmd.name).await?;
+            println!("{}", org.id);
+        }
+        SubCommand::Org(OrgCmd {
+            sub: OrgSubCommand::Delete(ref cmd),
+        }) => {
+            api_client.delete_org(&cmd.org_id).await?;
+        }
+        SubCommand::Org(OrgCmd {
+            sub: OrgSubCommand::Switch(ref cmd),
+        }) => {
+            let file = default_org_file(&client_yaml);
+            match &cmd.org_id {
+                Some(org) => {
+                    if !api_client.orgs().await?.iter().any(|o| &o.id == org) {
+                        return Err(format!("unknown org: {org}").into());
                     }
-                })
-                .do_output()?;
+                    std::fs::write(&file, org)?;
+                }
+                None if file.exists() => std::fs::remove_file(&file)?,
+                None => {}
+            }
         }
-        SubCommand::Client(ClientCmd {
-            client: client_id,
-            sub: ClientSubCommand::Powershell(ref cmd),
+        SubCommand::Status | SubCommand::Profiles(_) => unreachable!(),
+        SubCommand::User(UserCmd {
+            sub: UserSubCommand::List,
         }) => {
-            let client = api_client.new_client_unchecked(&client_id);
-            let flow = client
-                .schedule_flow("Windows.System.PowerShell", &cmd.command)
+            let result = api_client.users().await?;
+            print_rows(cli.format, &result)?;
+        }
+        SubCommand::User(UserCmd {
+            sub: UserSubCommand::Add(ref cmd),
+        }) => {
+            let mut password = String::new();
+            if cmd.password_stdin {
+                std::io::stdin().read_line(&mut password)?;
+            }
+            let roles: Vec<&str> = cmd.role.iter().map(String::as_str).collect();
+            let orgs: Vec<&str> = cmd.to_org.iter().map(String::as_str).collect();
+            api_client
+                .create_user(
+                    &cmd.name,
+                    password.trim_end_matches(['\r', '\n']),
+                    &roles,
+                    &orgs,
+                )
                 .await?;
-            log::debug!("Flow ID: {flow}");
-            flow.fetch()
-                .await?
-                .into_iter()
-                .fold::<ShellResult, _>(ShellResult::default(), |acc, item: ShellResult| {
-                    ShellResult {
-                        stdout: acc.stdout + &item.stdout,
-                        stderr: acc.stderr + &item.stderr,
-                        ..ShellResult::default()
-                    }
+        }
+        SubCommand::User(UserCmd {
+            sub: UserSubCommand::Grant(ref cmd),
+        }) => {
+            let roles: Vec<&str> = cmd.role.iter().map(String::as_str).collect();
+            let permissions: Vec<&str> = cmd.permission.iter().map(String::as_str).collect();
+            api_client.grant(&cmd.name, &roles, &permissions).await?;
+            let result = api_client.user_permissions(&cmd.name).await?;
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        }
+        SubCommand::User(UserCmd {
+            sub: UserSubCommand::Rm(ref cmd),
+        }) => {
+            api_client.delete_user(&cmd.name).await?;
+        }
+        SubCommand::Notebook(NotebookCmd {
+            sub: NotebookSubCommand::Create(ref cmd),
+        }) => {
+            let cells = cmd
+                .cells
+                .iter()
+                .map(|file| {
+                    let input = std::fs::read_to_string(file)?;
+                    Ok(match file.extension().and_then(|e| e.to_str()) {
+                        Some("vql") => NotebookCell::vql(&input),
+                        _ => NotebookCell::markdown(&input),
+                    })
                 })
-                .do_output()?;
+                .collect::<Result<Vec<_>, std::io::Error>>()?;
+            let notebook = api_client.create_notebook(&cmd.title, &cells).await?;
+            println!("{notebook}");
         }
-        SubCommand::Fetch(ref cmd) => {
-            let buf = api_client.fetch(&cmd.path).await?;
-
-            let mut output = std::fs::File::create(&cmd.output_file)?;
-            output.write_all(&buf)?;
-            output.flush()?;
+        SubCommand::Notebook(NotebookCmd {
+            sub: NotebookSubCommand::List(ref cmd),
+        }) => {
+            let filter = NotebookFilter {
+                creator: cmd.creator.clone(),
+                shared_with: cmd.shared_with.clone(),
+                hunt_id: cmd.hunt.clone(),
+                flow_id: cmd.flow.clone(),
+            };
+            let result = api_client.notebooks(&filter).await?;
+            print_rows(cli.format, &result)?;
+        }
+        SubCommand::Notebook(NotebookCmd {
+            sub: NotebookSubCommand::Export(ref cmd),
+        }) => {
+            let buf = api_client
+                .notebook_unchecked(&cmd.notebook)
+                .export(cmd.kind)
+                .await?;
+            std::fs::write(&cmd.output_file, buf)?;
+        }
+        SubCommand::Artifact(ArtifactCmd {
+            sub: ArtifactSubCommand::List(ref cmd),
+        }) => {
+            let result = match (&cmd.term, cmd.kind) {
+                (None, None) => api_client.artifacts().await?,
+                (term, kind) => {
+                    api_client
+                        .search_artifacts(term.as_deref().unwrap_or_default(), kind)
+                        .await?
+                }
+            };
+            print_rows(cli.format, &result)?;
+        }
+        SubCommand::Artifact(ArtifactCmd {
+            sub: ArtifactSubCommand::Show(ref cmd),
+        }) => {
+            print!("{}", api_client.artifact(&cmd.name).await?.raw);
+        }
+        SubCommand::Artifact(ArtifactCmd {
+            sub: ArtifactSubCommand::Push(ref cmd),
+        }) => {
+            let yaml = std::fs::read_to_string(&cmd.file)?;
+            if cmd.dry_run {
+                let definition = ArtifactDefinition::from_yaml(&yaml)
+                    .map_err(APIClientError::MalformedArtifact)?;
+                let result = api_client.verify_artifact(&yaml).await?;
+                if !result.is_ok() {
+                    return Err(
+                        format!("{}: {}", cmd.file.display(), result.errors.join("; ")).into(),
+                    );
+                }
+                let action = match api_client.artifact(&definition.name).await {
+                    Ok(_) => "replace",
+                    Err(APIClientError::UnknownArtifact(_)) => "add",
+                    Err(e) => return Err(e.into()),
+                };
+                println!("{action} {}", definition.name);
+                return Ok(());
+            }
+            let artifact = api_client.set_artifact(&yaml).await?;
+            println!("{}", artifact.name);
+        }
+        SubCommand::Artifact(ArtifactCmd {
+            sub: ArtifactSubCommand::Rm(ref cmd),
+        }) => {
+            api_client.delete_artifact(&cmd.name).await?;
+        }
+        SubCommand::Artifact(ArtifactCmd {
+            sub: ArtifactSubCommand::Verify(ref cmd),
+        }) => {
+            let result = api_client
+                .verify_artifact(&std::fs::read_to_string(&cmd.file)?)
+                .await?;
+            for warning in &result.warnings {
+                writeln!(std::io::stderr(), "warning: {warning}")?;
+            }
+            for error in &result.errors {
+                writeln!(std::io::stderr(), "error: {error}")?;
+            }
+            if !result.is_ok() {
+                return Err(format!("{}: verification failed", cmd.file.to_string_lossy()).into());
+            }
         }
     }
 
@@ -0,0 +1,249 @@
+use std::io::Write;
+
+use serde::Serialize;
+
+/// Output format for result rows
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Pretty-printed JSON array
+    #[default]
+    Json,
+    /// One JSON object per line
+    Jsonl,
+    Csv,
+    Yaml,
+    /// Aligned text columns
+    Table,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "jsonl" => Ok(Self::Jsonl),
+            "csv" => Ok(Self::Csv),
+            "yaml" => Ok(Self::Yaml),
+            "table" => Ok(Self::Table),
+            _ => Err(format!("unknown output format: {s}")),
+        }
+    }
+}
+
+/// Column names of all rows, in order of first appearance. Rows that
+/// are not objects are put into a single `value` column.
+fn columns(rows: &[serde_json::Value]) -> Vec<String> {
+    let mut columns: Vec<String> = vec![];
+    for row in rows {
+        let keys = match row {
+            serde_json::Value::Object(map) => map.keys().cloned().collect(),
+            _ => vec!["value".to_string()],
+        };
+        for key in keys {
+            if !columns.contains(&key) {
+                columns.push(key);
+            }
+        }
+    }
+    columns
+}
+
+/// Text representation of a single cell: strings are printed as-is,
+/// other values as compact JSON
+fn cell(row: &serde_json::Value, column: &str) -> String {
+    let value = match row {
+        serde_json::Value::Object(map) => map.get(column),
+        _ if column == "value" => Some(row),
+        _ => None,
+    };
+    match value {
+        None | Some(serde_json::Value::Null) => String::new(),
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+    }
+}
+
+/// Member of an object or, for numeric keys, element of an array
+fn child<'a>(value: &'a serde_json::Value, key: &str) -> Option<&'a serde_json::Value> {
+    match value {
+        serde_json::Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => value.get(key),
+    }
+}
+
+/// Look up a `.`-separated path such as `Exe.Path` or `Args.0`.
+/// Keys that contain dots themselves are matched as a whole first.
+fn lookup<'a>(value: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    if let Some(v) = child(value, path) {
+        return Some(v);
+    }
+    path.char_indices()
+        .filter(|(_, c)| *c == '.')
+        .find_map(|(i, _)| lookup(child(value, &path[..i])?, &path[i + 1..]))
+}
+
+/// Reduce rows to the given fields, which may be nested paths. Rows
+/// are returned as they are if no fields are given.
+pub fn select_fields(rows: Vec<serde_json::Value>, fields: &[String]) -> Vec<serde_json::Value> {
+    if fields.is_empty() {
+        return rows;
+    }
+    rows.iter()
+        .map(|row| select_row_fields(row, fields))
+        .collect()
+}
+
+/// Reduce a single row to the given fields, see [`select_fields`]
+pub fn select_row_fields(row: &serde_json::Value, fields: &[String]) -> serde_json::Value {
+    if fields.is_empty() {
+        return row.clone();
+    }
+    fields
+        .iter()
+        .map(|f| {
+            let value = lookup(row, f).cloned().unwrap_or_default();
+            (f.clone(), value)
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Print result rows to stdout in the given format
+pub fn print_rows<T: Serialize>(
+    format: OutputFormat,
+    rows: &[T],
+) -> Result<(), Box<dyn std::error::Error>> {
+    write_rows(&mut std::io::stdout().lock(), format, rows)
+}
+
+/// Write result rows in the given format
+pub fn write_rows<T: Serialize>(
+    out: &mut dyn Write,
+    format: OutputFormat,
+    rows: &[T],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rows = rows
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()?;
+    match format {
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&rows)?)?,
+        OutputFormat::Jsonl => {
+            for row in &rows {
+                writeln!(out, "{row}")?;
+            }
+        }
+        OutputFormat::Yaml => write!(out, "{}", serde_yaml::to_string(&rows)?)?,
+        OutputFormat::Csv => {
+            let columns = columns(&rows);
+            let header: Vec<_> = columns.iter().map(|c| csv_field(c)).collect();
+            writeln!(out, "{}", header.join(","))?;
+            for row in &rows {
+                let fields: Vec<_> = columns.iter().map(|c| csv_field(&cell(row, c))).collect();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+        }
+        OutputFormat::Table => {
+            let columns = columns(&rows);
+            let cells: Vec<Vec<String>> = rows
+                .iter()
+                .map(|row| columns.iter().map(|c| cell(row, c)).collect())
+                .collect();
+            let widths: Vec<usize> = columns
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    cells
+                        .iter()
+                        .map(|r| r[i].chars().count())
+                        .chain([c.chars().count()])
+                        .max()
+                        .unwrap_or_default()
+                })
+                .collect();
+            let line = |fields: &[String]| {
+                fields
+                    .iter()
+                    .zip(&widths)
+                    .map(|(f, w)| format!("{f:w$}"))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+                    .trim_end()
+                    .to_string()
+            };
+            writeln!(out, "{}", line(&columns))?;
+            for row in &cells {
+                writeln!(out, "{}", line(row))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writer for result rows that arrive one at a time. JSON, JSONL, and
+/// YAML output is written as rows arrive; CSV and table output need
+/// all rows to determine columns and widths and are written by
+/// [`finish`](Self::finish).
+pub struct RowWriter<'a> {
+    out: &'a mut dyn Write,
+    format: OutputFormat,
+    rows: Vec<serde_json::Value>,
+    count: usize,
+}
+
+impl<'a> RowWriter<'a> {
+    pub fn new(out: &'a mut dyn Write, format: OutputFormat) -> Self {
+        Self {
+            out,
+            format,
+            rows: vec![],
+            count: 0,
+        }
+    }
+
+    pub fn write(&mut self, row: serde_json::Value) -> Result<(), Box<dyn std::error::Error>> {
+        match self.format {
+            OutputFormat::Json => {
+                write!(self.out, "{}", if self.count == 0 { "[\n" } else { ",\n" })?;
+                for (i, line) in serde_json::to_string_pretty(&row)?.lines().enumerate() {
+                    if i > 0 {
+                        writeln!(self.out)?;
+                    }
+                    write!(self.out, "  {line}")?;
+                }
+            }
+            OutputFormat::Jsonl => writeln!(self.out, "{row}")?,
+            OutputFormat::Yaml => {
+                let yaml = serde_yaml::to_string(&[row])?;
+                match self.count {
+                    0 => write!(self.out, "{yaml}")?,
+                    _ => write!(self.out, "{}", yaml.trim_start_matches("---\n"))?,
+                }
+            }
+            OutputFormat::Csv | OutputFormat::Table => self.rows.push(row),
+        }
+        self.out.flush()?;
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
+        match (self.format, self.count) {
+            (OutputFormat::Json, 0) => writeln!(self.out, "[]")?,
+            (OutputFormat::Json, _) => writeln!(self.out, "\n]")?,
+            (OutputFormat::Jsonl, _) | (OutputFormat::Yaml, 1..) => {}
+            (OutputFormat::Yaml, 0) | (OutputFormat::Csv, _) | (OutputFormat::Table, _) => {
+                write_rows(self.out, self.format, &self.rows)?
+            }
+        }
+        Ok(())
+    }
+}
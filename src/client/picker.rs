@@ -0,0 +1,59 @@
+use std::io::IsTerminal;
+
+use velociraptor_api::{APIClient, ClientSummary};
+
+/// Check whether `s` is a full client ID such as `C.02749d29d382534f`,
+/// or `server`
+fn is_client_id(s: &str) -> bool {
+    s == "server"
+        || s.len() == 18 && s.starts_with("C.") && s[2..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn describe(client: &ClientSummary) -> String {
+    format!(
+        "{}  {}  {}  last seen {}",
+        client.client_id, client.hostname, client.os, client.last_seen_at
+    )
+}
+
+/// Resolve a client ID, partial client ID, or hostname to a client
+/// ID. If several clients match and both stdin and stderr are
+/// terminals, the user picks one of them.
+pub async fn resolve_client(
+    api_client: &APIClient,
+    term: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if is_client_id(term) {
+        return Ok(term.to_string());
+    }
+    let mut matches = api_client.search_clients(term).await?;
+    matches.extend(api_client.search_clients(&format!("host:{term}")).await?);
+    let mut seen = std::collections::HashSet::new();
+    matches.retain(|c| seen.insert(c.client_id.clone()));
+    matches.sort_by_key(|c| std::cmp::Reverse(c.last_seen_at));
+
+    match matches.as_slice() {
+        [] => Err(format!("no client matches {term}").into()),
+        [client] => Ok(client.client_id.clone()),
+        _ if std::io::stdin().is_terminal() && std::io::stderr().is_terminal() => {
+            let items: Vec<String> = matches.iter().map(describe).collect();
+            let selection = dialoguer::FuzzySelect::new()
+                .with_prompt(format!("{} clients match {term}", matches.len()))
+                .items(&items)
+                .default(0)
+                .interact_opt()?
+                .ok_or("no client selected")?;
+            Ok(matches[selection].client_id.clone())
+        }
+        _ => Err(format!(
+            "{} clients match {term}: {}",
+            matches.len(),
+            matches
+                .iter()
+                .map(|c| format!("{} ({})", c.client_id, c.hostname))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .into()),
+    }
+}
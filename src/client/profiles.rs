@@ -0,0 +1,158 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use velociraptor_api::{APIClient, APIClientConfig};
+
+/// Config entries that are replaced in `profiles show` output
+const REDACTED_KEYS: &[&str] = &["ca_certificate", "client_cert", "client_private_key"];
+
+/// Time after which a profile's connectivity test is considered failed
+/// unless `--timeout` is given
+const DEFAULT_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An API client config found in the config directory
+#[derive(Clone, Debug, Serialize)]
+pub struct Profile {
+    /// Name to be passed to `--instance`; none for the default profile
+    pub instance: Option<String>,
+    pub path: PathBuf,
+}
+
+impl Profile {
+    /// Default org, as set by `org switch` or in the config file
+    fn default_org(&self, config: &APIClientConfig) -> Option<String> {
+        std::fs::read_to_string(super::default_org_file(&self.path))
+            .ok()
+            .map(|o| o.trim().to_string())
+            .filter(|o| !o.is_empty())
+            .or_else(|| config.org_id().map(str::to_string))
+    }
+}
+
+/// Enumerate the profiles whose configs are stored in `dir`, the
+/// default profile first
+pub fn profiles(dir: &Path) -> std::io::Result<Vec<Profile>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut profiles = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        let Some(stem) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(".yaml"))
+        else {
+            continue;
+        };
+        let instance = match stem {
+            "apiclient" => None,
+            _ => match stem.strip_prefix("apiclient-") {
+                Some(instance) => Some(instance.to_string()),
+                None => continue,
+            },
+        };
+        profiles.push(Profile { instance, path });
+    }
+    profiles.sort_by(|a, b| a.instance.cmp(&b.instance));
+    Ok(profiles)
+}
+
+/// Row printed by `profiles list`
+#[derive(Serialize)]
+pub struct ProfileSummary {
+    instance: Option<String>,
+    path: PathBuf,
+    name: Option<String>,
+    server: Option<String>,
+    default_org: Option<String>,
+    certificate_expiry: Option<String>,
+    error: Option<String>,
+}
+
+pub fn summary(profile: &Profile) -> ProfileSummary {
+    let mut summary = ProfileSummary {
+        instance: profile.instance.clone(),
+        path: profile.path.clone(),
+        name: None,
+        server: None,
+        default_org: None,
+        certificate_expiry: None,
+        error: None,
+    };
+    match APIClientConfig::from_yaml_file(&profile.path) {
+        Ok(config) => {
+            summary.name = Some(config.name().to_string());
+            summary.server = Some(config.api_connection_string().to_string());
+            summary.default_org = profile.default_org(&config);
+            match config.certificate_expiry() {
+                Ok(expiry) => summary.certificate_expiry = Some(expiry.to_string()),
+                Err(e) => summary.error = Some(e.to_string()),
+            }
+        }
+        Err(e) => summary.error = Some(e.to_string()),
+    }
+    summary
+}
+
+/// Profile config with certificates and the private key redacted
+pub fn details(profile: &Profile) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let config = APIClientConfig::from_yaml_file(&profile.path)?;
+    let yaml: serde_json::Value = serde_yaml::from_reader(std::fs::File::open(&profile.path)?)?;
+    let mut details = serde_json::Map::new();
+    details.insert("instance".into(), profile.instance.clone().into());
+    details.insert("path".into(), profile.path.to_string_lossy().into());
+    if let serde_json::Value::Object(entries) = yaml {
+        for (key, value) in entries {
+            let value = if REDACTED_KEYS.contains(&key.as_str()) {
+                "<redacted>".into()
+            } else {
+                value
+            };
+            details.insert(key, value);
+        }
+    }
+    details.insert("default_org".into(), profile.default_org(&config).into());
+    details.insert(
+        "certificate_expiry".into(),
+        config
+            .certificate_expiry()
+            .map_or_else(|e| e.to_string(), |expiry| expiry.to_string())
+            .into(),
+    );
+    Ok(serde_json::Value::Object(details))
+}
+
+/// Row printed by `profiles test`
+#[derive(Serialize)]
+pub struct ProfileTest {
+    instance: Option<String>,
+    pub ok: bool,
+    detail: String,
+}
+
+/// Connect to the profile's server and query its version
+pub async fn test(profile: &Profile, timeout: Option<Duration>) -> ProfileTest {
+    let result = async {
+        let config = APIClientConfig::from_yaml_file(&profile.path)?;
+        let api_client =
+            APIClient::try_from(&config)?.with_timeout(timeout.unwrap_or(DEFAULT_TEST_TIMEOUT));
+        let info = api_client.server_info().await?;
+        Ok::<_, Box<dyn std::error::Error>>(format!(
+            "{} {} ({})",
+            config.api_connection_string(),
+            info.version,
+            info.commit
+        ))
+    }
+    .await;
+    ProfileTest {
+        instance: profile.instance.clone(),
+        ok: result.is_ok(),
+        detail: result.unwrap_or_else(|e| e.to_string()),
+    }
+}
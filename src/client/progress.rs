@@ -0,0 +1,54 @@
+use std::io::IsTerminal;
+use std::time::Duration;
+
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+
+use velociraptor_api::FlowSummary;
+
+/// Return `bar` if progress indication is enabled and standard error
+/// is a terminal, a hidden progress bar otherwise
+fn visible_if(enabled: bool, bar: ProgressBar) -> ProgressBar {
+    if enabled && std::io::stderr().is_terminal() {
+        bar
+    } else {
+        ProgressBar::hidden()
+    }
+}
+
+/// Spinner for a flow that is being waited for
+pub fn flow_spinner(enabled: bool, flow_id: &str) -> ProgressBar {
+    let bar = visible_if(enabled, ProgressBar::new_spinner());
+    bar.set_style(ProgressStyle::with_template("{spinner} {prefix} {elapsed} {msg}").unwrap());
+    bar.set_prefix(flow_id.to_string());
+    bar.enable_steady_tick(Duration::from_millis(100));
+    bar
+}
+
+/// Show a flow's state and collected rows and bytes
+pub fn update_flow(bar: &ProgressBar, summary: &FlowSummary) {
+    bar.set_message(format!(
+        "{}: {} rows, {} uploaded",
+        summary.state,
+        summary.total_collected_rows,
+        HumanBytes(summary.total_uploaded_bytes)
+    ));
+}
+
+/// Progress bar for a file download whose size is learnt once the
+/// first chunk has been received
+pub fn fetch_bar(enabled: bool) -> ProgressBar {
+    let bar = visible_if(enabled, ProgressBar::new(0));
+    bar.set_style(
+        ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} {binary_bytes_per_sec} {eta}")
+            .unwrap(),
+    );
+    bar
+}
+
+/// Update a download progress bar from a fetch progress callback
+pub fn update_fetch(bar: &ProgressBar, done: u64, total: Option<u64>) {
+    if let Some(total) = total {
+        bar.set_length(total);
+    }
+    bar.set_position(done);
+}
@@ -0,0 +1,231 @@
+use base64::prelude::*;
+
+/// Client shells that commands can be run in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Cmd,
+    Powershell,
+}
+
+/// Commands that pass local input to a remote shell command: the input
+/// is written to a temporary file on the client in base64-encoded
+/// chunks, decoded, and fed to the command's standard input.
+pub struct ShellScript {
+    /// Commands that each append a chunk to the temporary file
+    pub prelude: Vec<String>,
+    /// The command itself, reading from the temporary file
+    pub main: String,
+    /// Command that removes temporary files, if this can't be done by
+    /// `main` without losing the command's return code
+    pub cleanup: Option<String>,
+}
+
+impl Shell {
+    /// Artifact that runs commands in this shell
+    pub fn artifact(self) -> &'static str {
+        match self {
+            Shell::Bash => "Linux.Sys.BashShell",
+            Shell::Cmd => "Windows.System.CmdShell",
+            Shell::Powershell => "Windows.System.PowerShell",
+        }
+    }
+
+    /// Number of base64 characters passed per command, well below the
+    /// shell's command line length limit
+    fn chunk_size(self) -> usize {
+        match self {
+            Shell::Bash => 64 * 1024,
+            Shell::Cmd => 6 * 1024,
+            Shell::Powershell => 8 * 1024,
+        }
+    }
+
+    fn append(self, file: &str, chunk: &str) -> String {
+        match self {
+            Shell::Bash => format!("printf %s '{chunk}' >> '{file}'"),
+            Shell::Cmd => format!(r#"<nul set /p ="{chunk}" >> "%TEMP%\{file}.b64""#),
+            Shell::Powershell => {
+                format!(r#"Add-Content -NoNewline -Path "$env:TEMP\{file}" -Value '{chunk}'"#)
+            }
+        }
+    }
+
+    fn run(self, file: &str, command: &str) -> String {
+        match self {
+            Shell::Bash => {
+                format!("base64 -d '{file}' | {{ {command}\n}}; rc=$?; rm -f '{file}'; exit $rc")
+            }
+            Shell::Cmd => format!(
+                r#"certutil -f -decode "%TEMP%\{file}.b64" "%TEMP%\{file}" >nul && ({command}) < "%TEMP%\{file}""#
+            ),
+            // PowerShell pipes text rather than bytes, so the decoded
+            // input is redirected to a nested PowerShell through cmd.exe
+            Shell::Powershell => {
+                let utf16: Vec<u8> = command.encode_utf16().flat_map(u16::to_le_bytes).collect();
+                let encoded = BASE64_STANDARD.encode(utf16);
+                format!(
+                    r#"$vrFile = "$env:TEMP\{file}"; [IO.File]::WriteAllBytes("$vrFile.bin", [Convert]::FromBase64String([IO.File]::ReadAllText($vrFile))); cmd /c "powershell -NoProfile -NonInteractive -EncodedCommand {encoded} < `"$vrFile.bin`""; $vrRc = $LASTEXITCODE; Remove-Item $vrFile, "$vrFile.bin"; exit $vrRc"#
+                )
+            }
+        }
+    }
+
+    fn cleanup(self, file: &str) -> Option<String> {
+        match self {
+            Shell::Cmd => Some(format!(r#"del "%TEMP%\{file}.b64" "%TEMP%\{file}""#)),
+            _ => None,
+        }
+    }
+
+    /// Script that runs `command` with `input` as its standard input
+    pub fn with_input(self, command: &str, input: &[u8]) -> ShellScript {
+        let file = match self {
+            Shell::Bash => format!("/tmp/velociraptor-stdin-{:016x}", rand_id()),
+            _ => format!("velociraptor-stdin-{:016x}", rand_id()),
+        };
+        let encoded = BASE64_STANDARD.encode(input);
+        let mut chunks: Vec<&str> = encoded
+            .as_bytes()
+            .chunks(self.chunk_size())
+            .map(|c| std::str::from_utf8(c).unwrap())
+            .collect();
+        let last = chunks.pop().unwrap_or_default();
+        let mut prelude: Vec<String> = chunks.iter().map(|c| self.append(&file, c)).collect();
+        let main = match self {
+            // A chunk can't be appended in the same command line as
+            // the decoding step without exceeding cmd.exe's limit
+            Shell::Cmd => {
+                prelude.push(self.append(&file, last));
+                self.run(&file, command)
+            }
+            Shell::Bash => format!("{}\n{}", self.append(&file, last), self.run(&file, command)),
+            Shell::Powershell => {
+                format!("{}; {}", self.append(&file, last), self.run(&file, command))
+            }
+        };
+        ShellScript {
+            prelude,
+            main,
+            cleanup: self.cleanup(&file),
+        }
+    }
+}
+
+/// Identifier for temporary files that is unlikely to collide with
+/// those of concurrent invocations
+fn rand_id() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    hasher.write_u32(std::process::id());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHELLS: [Shell; 3] = [Shell::Bash, Shell::Cmd, Shell::Powershell];
+
+    /// Chunks appended by the script's commands, in order
+    fn chunks(shell: Shell, script: &ShellScript) -> Vec<String> {
+        let (start, end) = match shell {
+            Shell::Bash => ("printf %s '", '\''),
+            Shell::Cmd => (r#"set /p =""#, '"'),
+            Shell::Powershell => ("-Value '", '\''),
+        };
+        script
+            .prelude
+            .iter()
+            .chain([&script.main])
+            .filter_map(|cmd| {
+                let (_, rest) = cmd.split_once(start)?;
+                Some(rest[..rest.find(end).unwrap()].to_string())
+            })
+            .collect()
+    }
+
+    fn decoded(shell: Shell, script: &ShellScript) -> Vec<u8> {
+        BASE64_STANDARD
+            .decode(chunks(shell, script).concat())
+            .unwrap()
+    }
+
+    #[test]
+    fn empty_input() {
+        for shell in SHELLS {
+            let script = shell.with_input("sort", b"");
+            assert_eq!(chunks(shell, &script), [""], "{shell:?}");
+            assert!(decoded(shell, &script).is_empty(), "{shell:?}");
+        }
+    }
+
+    #[test]
+    fn single_chunk() {
+        for shell in SHELLS {
+            // Exactly one chunk of base64
+            let input = vec![b'x'; shell.chunk_size() / 4 * 3];
+            let script = shell.with_input("sort", &input);
+            let chunks = chunks(shell, &script);
+            assert_eq!(chunks.len(), 1, "{shell:?}");
+            assert_eq!(chunks[0].len(), shell.chunk_size(), "{shell:?}");
+            assert_eq!(decoded(shell, &script), input, "{shell:?}");
+        }
+    }
+
+    #[test]
+    fn multiple_chunks() {
+        for shell in SHELLS {
+            let input: Vec<u8> = (0..shell.chunk_size() * 2).map(|i| i as u8).collect();
+            let script = shell.with_input("sort", &input);
+            let chunks = chunks(shell, &script);
+            assert_eq!(chunks.len(), 3, "{shell:?}");
+            assert!(chunks.iter().all(|c| c.len() <= shell.chunk_size()));
+            assert_eq!(decoded(shell, &script), input, "{shell:?}");
+        }
+    }
+
+    #[test]
+    fn cmd_appends_in_prelude() {
+        let input = vec![0; Shell::Cmd.chunk_size()];
+        let script = Shell::Cmd.with_input("sort", &input);
+        assert_eq!(script.prelude.len(), 2);
+        assert!(script
+            .prelude
+            .iter()
+            .all(|cmd| cmd.starts_with("<nul set /p")));
+        assert!(script.main.starts_with("certutil -f -decode"));
+        assert!(script
+            .main
+            .contains(r#"(sort) < "%TEMP%\velociraptor-stdin-"#));
+        let file = script.main.split('"').nth(1).unwrap();
+        assert_eq!(
+            script.cleanup.as_deref().unwrap(),
+            format!(r#"del "{file}" "{}""#, file.strip_suffix(".b64").unwrap())
+        );
+        assert_eq!(decoded(Shell::Cmd, &script), input);
+    }
+
+    #[test]
+    fn powershell_encoded_command() {
+        let command = "Get-Content -Raw | Measure-Object -Character # ü";
+        let script = Shell::Powershell.with_input(command, b"input");
+        let (_, rest) = script.main.split_once("-EncodedCommand ").unwrap();
+        let encoded = rest.split(' ').next().unwrap();
+        let utf16: Vec<u16> = BASE64_STANDARD
+            .decode(encoded)
+            .unwrap()
+            .chunks(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(String::from_utf16(&utf16).unwrap(), command);
+        assert!(script.prelude.is_empty());
+        assert!(script.cleanup.is_none());
+    }
+}
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+use crate::row::null_as_default;
+use crate::{APIClient, APIClientError, Client, QueryOptions, VrTimestamp};
+
+/// Columns selected from the `clients()` plugin
+const CLIENT_COLUMNS: &str = r#"client_id, os_info.hostname AS hostname, os_info.fqdn AS fqdn,
+    os_info.system AS os, last_seen_at, last_ip, labels"#;
+
+/// Summary of a client as returned by the `clients()` plugin
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ClientSummary {
+    pub client_id: String,
+    pub hostname: String,
+    pub fqdn: String,
+    /// Operating system, e.g. `linux` or `windows`
+    pub os: String,
+    /// Time the client last contacted the server
+    pub last_seen_at: VrTimestamp,
+    pub last_ip: String,
+    #[serde(deserialize_with = "null_as_default")]
+    pub labels: Vec<String>,
+}
+
+impl APIClient {
+    /// Search for clients using the same syntax as the GUI's search
+    /// box, e.g. a client ID prefix, `host:web*`, or `label:servers`
+    pub async fn search_clients(&self, search: &str) -> Result<Vec<ClientSummary>, APIClientError> {
+        self.sync_query(
+            &format!("SELECT {CLIENT_COLUMNS} FROM clients(search=search)"),
+            &QueryOptions::builder()
+                .env(vec![("search".into(), search.into())])
+                .build(),
+        )
+        .await
+    }
+}
+
+impl Client<'_> {
+    /// Summary of the client, if it is known to the server
+    pub async fn summary(&self) -> Result<Option<ClientSummary>, APIClientError> {
+        Ok(self
+            .api_client
+            .sync_query(
+                &format!("SELECT {CLIENT_COLUMNS} FROM clients(client_id=client_id)"),
+                &QueryOptions::builder()
+                    .env(vec![("client_id".into(), self.client_id.clone())])
+                    .build(),
+            )
+            .await?
+            .into_iter()
+            .next())
+    }
+}
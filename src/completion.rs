@@ -0,0 +1,123 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use futures::future::BoxFuture;
+use futures::stream::StreamExt;
+
+use serde::Deserialize;
+
+use crate::{APIClient, APIClientError};
+
+/// A row from the `System.Flow.Completion` event queue
+#[derive(Clone, Debug, Deserialize)]
+pub struct FlowCompletion {
+    #[serde(rename = "ClientId")]
+    pub client_id: String,
+    #[serde(rename = "FlowId")]
+    pub flow_id: String,
+    /// The flow's context, including its request and final state
+    #[serde(rename = "Flow", default)]
+    pub flow: serde_json::Value,
+}
+
+impl FlowCompletion {
+    /// ID of the hunt that created the flow, if any
+    pub fn hunt_id(&self) -> Option<&str> {
+        self.flow
+            .pointer("/request/creator")
+            .and_then(|c| c.as_str())
+            .filter(|c| c.starts_with("H."))
+    }
+}
+
+/// What a completion callback is registered for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompletionKey {
+    /// A single flow. The callback is removed after it has been called.
+    Flow(String),
+    /// All flows of a client
+    Client(String),
+    /// All flows created by a hunt
+    Hunt(String),
+}
+
+impl CompletionKey {
+    fn matches(&self, c: &FlowCompletion) -> bool {
+        match self {
+            Self::Flow(id) => id == &c.flow_id,
+            Self::Client(id) => id == &c.client_id,
+            Self::Hunt(id) => c.hunt_id() == Some(id.as_str()),
+        }
+    }
+}
+
+type Callback = Arc<dyn Fn(FlowCompletion) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Registry of async callbacks that are invoked when flows complete.
+///
+/// All outstanding flows are tracked through a single subscription to
+/// `System.Flow.Completion`, driven by [`CompletionRegistry::run`].
+/// Callbacks can be registered and removed while it is running.
+#[derive(Default)]
+pub struct CompletionRegistry {
+    callbacks: Mutex<Vec<(CompletionKey, Callback)>>,
+}
+
+impl CompletionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a callback. It is run as a separate task, so slow
+    /// callbacks do not hold up the processing of other completions.
+    pub fn register<F, Fut>(&self, key: CompletionKey, callback: F)
+    where
+        F: Fn(FlowCompletion) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let callback: Callback = Arc::new(move |c| Box::pin(callback(c)));
+        self.callbacks.lock().unwrap().push((key, callback));
+    }
+
+    /// Remove all callbacks registered for `key`
+    pub fn unregister(&self, key: &CompletionKey) {
+        self.callbacks.lock().unwrap().retain(|(k, _)| k != key);
+    }
+
+    /// Number of registered callbacks
+    pub fn len(&self) -> usize {
+        self.callbacks.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Dispatch a single completion event to all matching callbacks
+    fn dispatch(&self, completion: FlowCompletion) {
+        let mut callbacks = self.callbacks.lock().unwrap();
+        for (_, callback) in callbacks.iter().filter(|(k, _)| k.matches(&completion)) {
+            tokio::spawn(callback(completion.clone()));
+        }
+        callbacks.retain(|(k, _)| !matches!(k, CompletionKey::Flow(_)) || !k.matches(&completion));
+    }
+
+    /// Subscribe to `System.Flow.Completion` and dispatch events until
+    /// the subscription ends
+    pub async fn run(&self, api_client: &APIClient) -> Result<(), APIClientError> {
+        let events = api_client
+            .watch_events::<FlowCompletion>("System.Flow.Completion", None)
+            .await?;
+        let mut events = std::pin::pin!(events);
+        while let Some(completion) = events.next().await {
+            let completion = completion?;
+            log::debug!(
+                "flow completed: {} / {}",
+                completion.client_id,
+                completion.flow_id
+            );
+            self.dispatch(completion);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,554 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use async_stream::try_stream;
+
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use sha2::{Digest, Sha256};
+
+use time::OffsetDateTime;
+
+use tokio::time::{sleep, Duration};
+
+use crate::proto::*;
+use crate::{APIClient, APIClientError, QueryOptions, VrTimestamp};
+
+/// Length of the time slices in which stored events are retrieved
+const EVENT_PAGE_SECONDS: i64 = 3600;
+
+/// Time given to a subscription to be established before stored rows
+/// are replayed
+const SUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+/// Where stored rows of a followed event artifact are read from
+#[derive(Clone, Copy)]
+enum EventSource {
+    Server,
+    Clients,
+}
+
+/// Position in an event artifact, used to pass on each row only once
+/// when stored rows are replayed and overlap with rows delivered by a
+/// subscription.
+///
+/// The position consists of the most recent `_ts` seen and digests of
+/// the rows seen with that timestamp. Rows are expected to arrive in
+/// order of `_ts`; rows that are older than the position, or that are
+/// identical to one seen with the same timestamp, are considered
+/// duplicates. Rows without `_ts` are always considered new.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct EventCursor {
+    ts: Option<f64>,
+    #[serde(default)]
+    seen: HashSet<String>,
+}
+
+impl EventCursor {
+    /// A cursor before all rows
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cursor before the rows with timestamp `ts`
+    pub fn starting_at(ts: f64) -> Self {
+        Self {
+            ts: Some(ts),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Timestamp of the most recent row seen
+    pub fn ts(&self) -> Option<f64> {
+        self.ts
+    }
+
+    /// Check whether `row` comes after the position, and advance the
+    /// position past it if so
+    pub fn is_new(&mut self, row: &serde_json::Value) -> bool {
+        let Some(ts) = row.get("_ts").and_then(|v| v.as_f64()) else {
+            return true;
+        };
+        let digest = format!("{:x}", Sha256::digest(row.to_string()))[..16].to_string();
+        match self.ts {
+            Some(last) if ts < last => false,
+            Some(last) if ts == last => self.seen.insert(digest),
+            _ => {
+                self.ts = Some(ts);
+                self.seen = HashSet::from([digest]);
+                true
+            }
+        }
+    }
+}
+
+/// An event artifact enabled for server monitoring, along with its
+/// parameters
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MonitoredArtifact {
+    pub name: String,
+    pub parameters: Vec<(String, String)>,
+}
+
+/// A record from the server's audit log (`Server.Audit.Logs`)
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditRecord {
+    /// Time of the operation
+    #[serde(rename = "_ts", default)]
+    pub timestamp: VrTimestamp,
+    /// Operation performed, e.g. `ScheduleHunt` or `user_create`
+    #[serde(default)]
+    pub operation: String,
+    /// User who performed the operation
+    #[serde(default)]
+    pub principal: String,
+    /// Operation-specific details
+    #[serde(default)]
+    pub details: serde_json::Value,
+}
+
+/// Changes in the client population
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClientLifecycleEvent {
+    /// A new client has enrolled
+    Enrolled { client_id: String },
+    /// A client has contacted the server after having been offline
+    /// for longer than the configured threshold
+    Returned {
+        client_id: String,
+        offline_for: Duration,
+    },
+}
+
+/// Server monitoring configuration as returned by
+/// `get_server_monitoring()`
+#[derive(Default, Deserialize)]
+struct MonitoringTable {
+    #[serde(default)]
+    artifacts: Option<Vec<String>>,
+    #[serde(default)]
+    specs: Option<Vec<MonitoringSpec>>,
+}
+
+#[derive(Default, Deserialize)]
+struct MonitoringSpec {
+    artifact: String,
+    #[serde(default)]
+    parameters: Option<MonitoringParameters>,
+}
+
+#[derive(Default, Deserialize)]
+struct MonitoringParameters {
+    #[serde(default)]
+    env: Option<Vec<MonitoringEnv>>,
+}
+
+#[derive(Deserialize)]
+struct MonitoringEnv {
+    key: String,
+    #[serde(default)]
+    value: String,
+}
+
+impl APIClient {
+    /// Push rows into an artifact's event queue, e.g. to inject alerts
+    /// from third-party systems. This requires the `PUBLISH` permission
+    /// for the artifact.
+    pub async fn push_events<T: Serialize>(
+        &self,
+        artifact: &str,
+        rows: &[T],
+    ) -> Result<(), APIClientError> {
+        let response = serde_json::to_string(rows).map_err(APIClientError::MalformedResponse)?;
+        let start = std::time::Instant::now();
+        let response = self
+            .api_client()
+            .await
+            .map_err(APIClientError::Transport)?
+            .write_event(self.request(VqlResponse {
+                response,
+                query: Some(VqlRequest {
+                    name: artifact.into(),
+                    vql: "".into(),
+                }),
+                total_rows: rows.len() as u64,
+                ..VqlResponse::default()
+            }))
+            .await;
+        self.rpc_result(response)?;
+        crate::instrumentation::rpc_completed("WriteEvent", start);
+        Ok(())
+    }
+
+    /// Retrieve audit log records for operations performed in the given
+    /// time range
+    pub async fn audit_log(
+        &self,
+        time_range: Range<OffsetDateTime>,
+    ) -> Result<Vec<AuditRecord>, APIClientError> {
+        self.events_between("Server.Audit.Logs", time_range.start, time_range.end)
+            .try_collect()
+            .await
+    }
+
+    /// Retrieve rows stored for a server event artifact between `start`
+    /// and `end`. The time range is queried in slices, so large ranges
+    /// can be processed without holding all rows in memory.
+    pub fn events_between<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+        artifact: &'a str,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> impl Stream<Item = Result<T, APIClientError>> + 'a {
+        try_stream! {
+            let (mut from, end) = (start.unix_timestamp(), end.unix_timestamp());
+            while from < end {
+                let to = (from + EVENT_PAGE_SECONDS).min(end);
+                // Both bounds are inclusive, so all but the last page
+                // end before the next one starts
+                let last = if to < end { to - 1 } else { to };
+                log::debug!("Fetching {artifact} events {from}..={last}");
+                let rows = self
+                    .query_stream::<T>(
+                        r#"SELECT * FROM source(artifact=artifact,
+                                                start_time=int(int=start),
+                                                end_time=int(int=end))"#,
+                        &QueryOptions::builder()
+                            .env(vec![
+                                ("artifact".into(), artifact.into()),
+                                ("start".into(), from.to_string()),
+                                ("end".into(), last.to_string()),
+                            ])
+                            .build(),
+                    )
+                    .await?;
+                let mut rows = std::pin::pin!(rows);
+                while let Some(row) = rows.next().await {
+                    yield row?;
+                }
+                from = to;
+            }
+        }
+    }
+
+    /// Subscribe to client enrollments and to clients returning after
+    /// having been offline for longer than `offline_threshold`.
+    ///
+    /// Last-seen times are taken from the client index when the stream
+    /// is created and are then tracked through `Server.Internal.ClientPing`.
+    pub async fn client_lifecycle_events(
+        &self,
+        offline_threshold: Duration,
+    ) -> Result<impl Stream<Item = Result<ClientLifecycleEvent, APIClientError>> + '_, APIClientError>
+    {
+        #[derive(Deserialize)]
+        struct Seen {
+            client_id: String,
+            last_seen_at: u64,
+        }
+        #[derive(Deserialize)]
+        struct Event {
+            #[serde(rename = "ClientId")]
+            client_id: String,
+        }
+
+        let mut last_seen: HashMap<String, u64> = self
+            .sync_query::<Seen>(
+                r#"SELECT client_id, last_seen_at FROM clients()"#,
+                &QueryOptions::builder().build(),
+            )
+            .await?
+            .into_iter()
+            .map(|s| (s.client_id, s.last_seen_at / 1_000_000))
+            .collect();
+        let enrollments = self
+            .watch_events::<Event>("Server.Internal.Enrollment", None)
+            .await?
+            .map_ok(|e| (true, e.client_id));
+        let pings = self
+            .watch_events::<Event>("Server.Internal.ClientPing", None)
+            .await?
+            .map_ok(|e| (false, e.client_id));
+
+        Ok(try_stream! {
+            let mut events = std::pin::pin!(stream::select(enrollments, pings));
+            while let Some(event) = events.next().await {
+                let (enrolled, client_id) = event?;
+                let now = OffsetDateTime::now_utc().unix_timestamp() as u64;
+                let previous = last_seen.insert(client_id.clone(), now);
+                if enrolled {
+                    yield ClientLifecycleEvent::Enrolled { client_id };
+                } else if let Some(previous) = previous {
+                    let offline_for = Duration::from_secs(now.saturating_sub(previous));
+                    if offline_for > offline_threshold {
+                        yield ClientLifecycleEvent::Returned { client_id, offline_for };
+                    }
+                }
+            }
+        })
+    }
+
+    /// Retrieve the set of event artifacts enabled for server monitoring
+    pub async fn server_monitoring(&self) -> Result<Vec<MonitoredArtifact>, APIClientError> {
+        #[derive(Deserialize)]
+        struct Row {
+            config: Option<MonitoringTable>,
+        }
+        let table = self
+            .sync_query::<Row>(
+                r#"SELECT get_server_monitoring() AS config FROM scope()"#,
+                &QueryOptions::builder().build(),
+            )
+            .await?
+            .into_iter()
+            .next()
+            .and_then(|r| r.config)
+            .unwrap_or_default();
+        let specs = table.specs.unwrap_or_default();
+        Ok(table
+            .artifacts
+            .unwrap_or_default()
+            .into_iter()
+            .map(|name| {
+                let parameters = specs
+                    .iter()
+                    .filter(|s| s.artifact == name)
+                    .flat_map(|s| s.parameters.iter())
+                    .flat_map(|p| p.env.iter().flatten())
+                    .map(|e| (e.key.clone(), e.value.clone()))
+                    .collect();
+                MonitoredArtifact { name, parameters }
+            })
+            .collect())
+    }
+
+    /// Replace the set of event artifacts enabled for server monitoring
+    pub async fn set_server_monitoring(
+        &self,
+        artifacts: &[MonitoredArtifact],
+    ) -> Result<(), APIClientError> {
+        let config = serde_json::json!({
+            "artifacts": artifacts.iter().map(|a| &a.name).collect::<Vec<_>>(),
+            "specs": artifacts.iter().map(|a| serde_json::json!({
+                "artifact": a.name,
+                "parameters": {
+                    "env": a.parameters.iter().map(|(key, value)| serde_json::json!({
+                        "key": key,
+                        "value": value,
+                    })).collect::<Vec<_>>(),
+                },
+            })).collect::<Vec<_>>(),
+        });
+        self.sync_query::<serde_json::Value>(
+            r#"SELECT set_server_monitoring(value=parse_json(data=config)) FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![("config".into(), config.to_string())])
+                .build(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Subscribe to a server event queue such as `Server.Audit.Logs`
+    /// or `System.Flow.Completion`. The returned stream yields event
+    /// rows as they are emitted and ends only when the connection is
+    /// closed.
+    pub async fn watch_events<T: DeserializeOwned>(
+        &self,
+        artifact: &str,
+        org_id: Option<&str>,
+    ) -> Result<impl Stream<Item = Result<T, APIClientError>>, APIClientError> {
+        self.query_stream(
+            r#"SELECT * FROM watch_monitoring(artifact=artifact)"#,
+            &QueryOptions::builder()
+                .env(vec![("artifact".into(), artifact.into())])
+                .org_id(org_id.map(String::from))
+                .build(),
+        )
+        .await
+    }
+
+    /// Follow a client event artifact such as
+    /// `Windows.Events.ProcessCreation` across all clients.
+    ///
+    /// If the connection to the server is lost, it is re-established
+    /// automatically. Events that have been stored in the meantime are
+    /// replayed, starting from the timestamp of the last event seen.
+    /// The stream only ends if the rows cannot be deserialized, or if
+    /// the client's [`RetryPolicy`](crate::RetryPolicy) limits the
+    /// number of retries.
+    pub fn follow_client_events<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+        artifact: &'a str,
+        org_id: Option<&'a str>,
+    ) -> impl Stream<Item = Result<T, APIClientError>> + 'a {
+        let rows = self.follow_events(
+            "follow_client_events",
+            artifact,
+            org_id,
+            EventSource::Clients,
+            EventCursor::new(),
+        );
+        try_stream! {
+            let mut rows = std::pin::pin!(rows);
+            while let Some(row) = rows.next().await {
+                yield serde_json::from_value(row?).map_err(APIClientError::MalformedResponse)?;
+            }
+        }
+    }
+
+    /// Follow a server event artifact such as `Server.Audit.Logs`,
+    /// starting after the position recorded in `cursor`. Rows stored
+    /// since then are replayed first.
+    ///
+    /// Like [`follow_client_events`](Self::follow_client_events), the
+    /// subscription is re-established after losing the connection,
+    /// and the stream only ends if the client's
+    /// [`RetryPolicy`](crate::RetryPolicy) limits the number of
+    /// retries.
+    pub fn follow_server_events<'a>(
+        &'a self,
+        artifact: &'a str,
+        cursor: EventCursor,
+    ) -> impl Stream<Item = Result<serde_json::Value, APIClientError>> + 'a {
+        self.follow_events(
+            "follow_server_events",
+            artifact,
+            None,
+            EventSource::Server,
+            cursor,
+        )
+    }
+
+    /// Subscribe to an event artifact, replaying stored rows newer than
+    /// `cursor` and reconnecting after the connection is lost.
+    ///
+    /// The subscription is established before stored rows are
+    /// replayed, so that no rows are missed in between; rows delivered
+    /// both ways are passed on only once.
+    fn follow_events<'a>(
+        &'a self,
+        operation: &'static str,
+        artifact: &'a str,
+        org_id: Option<&'a str>,
+        source: EventSource,
+        mut cursor: EventCursor,
+    ) -> impl Stream<Item = Result<serde_json::Value, APIClientError>> + 'a {
+        try_stream! {
+            let mut backoff = self.retry.initial_backoff;
+            let mut retries = 0;
+            loop {
+                let mut last_error = None;
+                let watch = self.watch_events::<serde_json::Value>(artifact, org_id);
+                let mut watch = std::pin::pin!(watch);
+                let mut subscribed = None;
+                if let Some(since) = cursor.ts() {
+                    tokio::select! {
+                        events = &mut watch => subscribed = Some(events),
+                        _ = sleep(SUBSCRIBE_DELAY) => {}
+                    }
+                    let rows = match source {
+                        EventSource::Server => self
+                            .events_between::<serde_json::Value>(
+                                artifact,
+                                OffsetDateTime::from_unix_timestamp(since.floor() as i64)
+                                    .unwrap_or(OffsetDateTime::UNIX_EPOCH),
+                                OffsetDateTime::now_utc(),
+                            )
+                            .boxed(),
+                        EventSource::Clients => {
+                            stream::once(self.backfill_client_events(artifact, org_id, since))
+                                .try_flatten()
+                                .boxed()
+                        }
+                    };
+                    let mut rows = std::pin::pin!(rows);
+                    while let Some(row) = rows.next().await {
+                        let row = match row {
+                            Ok(row) => row,
+                            Err(e) => {
+                                log::warn!("backfill of {artifact} failed: {e}");
+                                last_error = Some(e);
+                                break;
+                            }
+                        };
+                        if cursor.is_new(&row) {
+                            yield row;
+                        }
+                    }
+                }
+                let events = match subscribed {
+                    Some(events) => events,
+                    None => watch.await,
+                };
+                match events {
+                    Ok(events) => {
+                        let mut events = std::pin::pin!(events);
+                        while let Some(row) = events.next().await {
+                            let row = match row {
+                                Ok(row) => row,
+                                Err(e) => {
+                                    log::warn!("watching {artifact} failed: {e}");
+                                    last_error = Some(e);
+                                    break;
+                                }
+                            };
+                            backoff = self.retry.initial_backoff;
+                            retries = 0;
+                            if cursor.is_new(&row) {
+                                yield row;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        log::warn!("watching {artifact} failed: {e}");
+                        last_error = Some(e);
+                    }
+                }
+                if self.retry.max_retries.is_some_and(|max| retries >= max) {
+                    log::warn!("following {artifact}: giving up after {retries} retries");
+                    Err(last_error.unwrap_or_else(|| {
+                        APIClientError::Status(tonic::Status::unavailable(format!(
+                            "event stream for {artifact} closed"
+                        )))
+                    }))?;
+                }
+                retries += 1;
+                log::debug!("reconnecting to {artifact} in {backoff:?}");
+                self.retrying(operation, retries, backoff);
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(self.retry.max_backoff);
+            }
+        }
+    }
+
+    /// Fetch client events stored at or after `since` from all clients. Rows
+    /// are streamed, so that a long outage does not require holding
+    /// all missed events in memory.
+    async fn backfill_client_events(
+        &self,
+        artifact: &str,
+        org_id: Option<&str>,
+        since: f64,
+    ) -> Result<impl Stream<Item = Result<serde_json::Value, APIClientError>>, APIClientError> {
+        self.query_stream(
+            r#"SELECT * FROM foreach(
+                 row={ SELECT client_id FROM clients() },
+                 query={ SELECT * FROM source(client_id=client_id,
+                                              artifact=artifact,
+                                              start_time=int(int=since))
+                         WHERE _ts >= float(float=since) })
+               ORDER BY _ts"#,
+            &QueryOptions::builder()
+                .env(vec![
+                    ("artifact".into(), artifact.into()),
+                    ("since".into(), since.to_string()),
+                ])
+                .org_id(org_id.map(String::from))
+                .build(),
+        )
+        .await
+    }
+}
@@ -0,0 +1,537 @@
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use base64::prelude::*;
+
+use bytes::Bytes;
+
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+
+use serde::{Deserialize, Serialize};
+
+use sha2::{Digest, Sha256};
+
+use thiserror::Error;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use tonic::transport::Channel;
+
+use crate::proto::*;
+use crate::{APIClient, APIClientError, QueryOptions, VfsPath, VrTimestamp};
+
+/// Number of bytes sent per query in uploads
+const UPLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Number of bytes fetched per range in parallel downloads
+const RANGE_SIZE: u64 = 1024 * 1024;
+
+/// An entry in the server's filestore
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FilestoreEntry {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "OSPath")]
+    pub path: String,
+    #[serde(rename = "Size")]
+    pub size: u64,
+    #[serde(rename = "Mtime")]
+    pub mtime: VrTimestamp,
+    #[serde(rename = "IsDir")]
+    pub is_dir: bool,
+}
+
+/// Hashes of a file in the server's filestore
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct FileHashes {
+    #[serde(rename = "MD5")]
+    pub md5: String,
+    #[serde(rename = "SHA1")]
+    pub sha1: String,
+    #[serde(rename = "SHA256")]
+    pub sha256: String,
+}
+
+/// Metadata of a file in the server's filestore
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FilestoreStat {
+    #[serde(rename = "Size")]
+    pub size: u64,
+    #[serde(rename = "Mtime")]
+    pub mtime: VrTimestamp,
+    /// Hashes of the file, only retrieved by
+    /// [`APIClient::stat_with_hash`]
+    #[serde(rename = "Hash", default)]
+    pub hashes: Option<FileHashes>,
+}
+
+/// Mismatch between the hash stored on the server and the hash of
+/// the downloaded data
+#[derive(Debug, Error)]
+#[error("SHA256 mismatch for {path}: expected {expected}, got {actual}")]
+pub struct IntegrityError {
+    pub path: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Summary of a verified download
+#[derive(Clone, Debug)]
+pub struct VerifiedFetch {
+    /// Number of bytes written
+    pub size: u64,
+    /// SHA256 of the downloaded data, hex-encoded
+    pub sha256: String,
+    /// SHA256 recorded by the server when a flow uploaded the file,
+    /// if any
+    pub expected: Option<String>,
+}
+
+/// `AsyncWrite` adapter that computes the SHA256 of all data written
+struct HashingWriter<'a, W> {
+    inner: &'a mut W,
+    hasher: Sha256,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for HashingWriter<'_, W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let poll = Pin::new(&mut *self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.hasher.update(&buf[..n]);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut *self.inner).poll_shutdown(cx)
+    }
+}
+
+impl APIClient {
+    /// List a directory in the server's filestore, e.g. `downloads` or
+    /// `clients/C.XXXXXXXXXXXXXXXX/collections`
+    pub async fn ls<P: Into<VfsPath>>(
+        &self,
+        path: P,
+    ) -> Result<Vec<FilestoreEntry>, APIClientError> {
+        self.sync_query(
+            r#"SELECT Name, OSPath, Size, Mtime, IsDir
+               FROM glob(globs="*", root=path, accessor="fs")"#,
+            &QueryOptions::builder()
+                .env(vec![("path".into(), path.into().to_vql_path())])
+                .build(),
+        )
+        .await
+    }
+
+    /// Retrieve size and modification time of a file in the server's
+    /// filestore
+    pub async fn stat<P: Into<VfsPath>>(&self, path: P) -> Result<FilestoreStat, APIClientError> {
+        self.stat_query(path.into(), "").await
+    }
+
+    /// Like `stat`, but also compute the hashes of the file. This makes
+    /// the server read the whole file.
+    pub async fn stat_with_hash<P: Into<VfsPath>>(
+        &self,
+        path: P,
+    ) -> Result<FilestoreStat, APIClientError> {
+        self.stat_query(path.into(), r#", hash(path=OSPath, accessor="fs") AS Hash"#)
+            .await
+    }
+
+    async fn stat_query(
+        &self,
+        path: VfsPath,
+        columns: &str,
+    ) -> Result<FilestoreStat, APIClientError> {
+        let path = path.to_vql_path();
+        self.sync_query(
+            &format!(r#"SELECT Size, Mtime{columns} FROM stat(filename=path, accessor="fs")"#),
+            &QueryOptions::builder()
+                .env(vec![("path".into(), path.clone())])
+                .build(),
+        )
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| APIClientError::IO(std::io::Error::new(std::io::ErrorKind::NotFound, path)))
+    }
+
+    /// Fetch downloadable file from Velociraptor server, writing it to
+    /// `writer` as it arrives. Returns the number of bytes written.
+    pub async fn fetch_to<P, W>(&self, path: P, writer: &mut W) -> Result<u64, APIClientError>
+    where
+        P: Into<VfsPath>,
+        W: AsyncWrite + Unpin,
+    {
+        self.write_chunks(path.into(), writer, None, |_, _| {})
+            .await
+    }
+
+    /// Like `fetch_to`, but after each chunk, call `progress` with the
+    /// number of bytes written so far and the total size of the file.
+    pub async fn fetch_to_with_progress<P, W, F>(
+        &self,
+        path: P,
+        writer: &mut W,
+        progress: F,
+    ) -> Result<u64, APIClientError>
+    where
+        P: Into<VfsPath>,
+        W: AsyncWrite + Unpin,
+        F: FnMut(u64, Option<u64>),
+    {
+        let path = path.into();
+        let total = self.stat(&path).await?.size;
+        self.write_chunks(path, writer, Some(total), progress).await
+    }
+
+    async fn write_chunks<W, F>(
+        &self,
+        path: VfsPath,
+        writer: &mut W,
+        total: Option<u64>,
+        mut progress: F,
+    ) -> Result<u64, APIClientError>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(u64, Option<u64>),
+    {
+        let mut chunks = std::pin::pin!(self.fetch_stream(path));
+        let mut offset = 0;
+        while let Some(chunk) = chunks.try_next().await? {
+            writer.write_all(&chunk).await.map_err(APIClientError::IO)?;
+            offset += chunk.len() as u64;
+            progress(offset, total);
+        }
+        writer.flush().await.map_err(APIClientError::IO)?;
+        Ok(offset)
+    }
+
+    /// Fetch downloadable file from Velociraptor server as a stream of
+    /// chunks, without buffering the whole file
+    pub fn fetch_stream<P: Into<VfsPath>>(
+        &self,
+        path: P,
+    ) -> impl Stream<Item = Result<Bytes, APIClientError>> + '_ {
+        let request = VfsFileBuffer {
+            components: path.into().components().to_vec(),
+            length: self.chunk_size,
+            ..VfsFileBuffer::default()
+        };
+        stream::try_unfold((None, 0), move |(api_client, offset)| {
+            let request = VfsFileBuffer {
+                offset,
+                ..request.clone()
+            };
+            async move {
+                let mut api_client = match api_client {
+                    Some(c) => c,
+                    None => self.api_client().await.map_err(APIClientError::Transport)?,
+                };
+                let start = std::time::Instant::now();
+                let response = api_client.vfs_get_buffer(self.request(request)).await;
+                let response = self.rpc_result(response)?.into_inner();
+                crate::instrumentation::rpc_completed("VFSGetBuffer", start);
+                match response.data.len() {
+                    0 => Ok(None),
+                    len => Ok(Some((
+                        Bytes::from(response.data),
+                        (Some(api_client), offset + len as u64),
+                    ))),
+                }
+            }
+        })
+    }
+
+    /// Like `fetch_to_with_progress`, but compute the SHA256 of the
+    /// downloaded data and compare it with the hash the server recorded
+    /// in the flow's upload metadata, if any. Files that were not
+    /// uploaded by a flow are fetched without comparison.
+    pub async fn fetch_verified_to<P, W, F>(
+        &self,
+        path: P,
+        writer: &mut W,
+        progress: F,
+    ) -> Result<VerifiedFetch, APIClientError>
+    where
+        P: Into<VfsPath>,
+        W: AsyncWrite + Unpin,
+        F: FnMut(u64, Option<u64>),
+    {
+        let path = path.into();
+        let expected = self.recorded_sha256(&path).await?;
+        let mut writer = HashingWriter {
+            inner: writer,
+            hasher: Sha256::new(),
+        };
+        let size = self
+            .fetch_to_with_progress(&path, &mut writer, progress)
+            .await?;
+        let sha256 = format!("{:x}", writer.hasher.finalize());
+        match &expected {
+            Some(h) if !h.eq_ignore_ascii_case(&sha256) => {
+                Err(APIClientError::Integrity(IntegrityError {
+                    path: path.to_string(),
+                    expected: h.clone(),
+                    actual: sha256,
+                }))
+            }
+            _ => Ok(VerifiedFetch {
+                size,
+                sha256,
+                expected,
+            }),
+        }
+    }
+
+    /// SHA256 recorded in the upload metadata of the flow that
+    /// uploaded `path`, if any
+    async fn recorded_sha256(&self, path: &VfsPath) -> Result<Option<String>, APIClientError> {
+        #[derive(Deserialize)]
+        struct Upload {
+            #[serde(rename = "_Components", default)]
+            components: Vec<String>,
+            #[serde(default)]
+            vfs_path: String,
+            #[serde(alias = "Sha256", default)]
+            sha256: String,
+        }
+        let [clients, client_id, collections, flow_id, uploads, ..] = path.components() else {
+            return Ok(None);
+        };
+        if (clients.as_str(), collections.as_str(), uploads.as_str())
+            != ("clients", "collections", "uploads")
+        {
+            return Ok(None);
+        }
+        let vql_path = path.to_vql_path();
+        Ok(self
+            .sync_query::<Upload>(
+                "SELECT * FROM uploads(client_id=client_id, flow_id=flow_id)",
+                &QueryOptions::builder()
+                    .env(vec![
+                        ("client_id".into(), client_id.clone()),
+                        ("flow_id".into(), flow_id.clone()),
+                    ])
+                    .build(),
+            )
+            .await?
+            .into_iter()
+            .find(|u| u.components == path.components() || u.vfs_path == vql_path)
+            .map(|u| u.sha256)
+            .filter(|h| !h.is_empty()))
+    }
+
+    /// Like `fetch_to_with_progress`, but fetch up to `concurrency`
+    /// ranges of the file in parallel over a single connection. This
+    /// improves throughput on high-latency links.
+    pub async fn fetch_parallel_to<P, W, F>(
+        &self,
+        path: P,
+        writer: &mut W,
+        concurrency: usize,
+        mut progress: F,
+    ) -> Result<u64, APIClientError>
+    where
+        P: Into<VfsPath>,
+        W: AsyncWrite + Unpin,
+        F: FnMut(u64, Option<u64>),
+    {
+        let path = path.into();
+        let total = self.stat(&path).await?.size;
+
+        let api_client = self.api_client().await.map_err(APIClientError::Transport)?;
+        let mut ranges = stream::iter((0..total).step_by(RANGE_SIZE as usize))
+            .map(|offset| {
+                read_range(
+                    self,
+                    api_client.clone(),
+                    path.components(),
+                    offset,
+                    RANGE_SIZE.min(total - offset),
+                )
+            })
+            .buffered(concurrency.max(1));
+
+        let mut done = 0;
+        while let Some(data) = ranges.try_next().await? {
+            writer.write_all(&data).await.map_err(APIClientError::IO)?;
+            done += data.len() as u64;
+            progress(done, Some(total));
+        }
+        writer.flush().await.map_err(APIClientError::IO)?;
+        Ok(done)
+    }
+
+    /// List all files in the filestore matching a glob pattern such as
+    /// `downloads/C.XXXXXXXXXXXXXXXX/**/*.zip`
+    pub async fn glob(&self, pattern: &str) -> Result<Vec<VfsPath>, APIClientError> {
+        #[derive(Deserialize)]
+        struct Match {
+            #[serde(rename = "Components")]
+            components: Vec<String>,
+        }
+        let matches: Vec<Match> = self
+            .sync_query(
+                r#"SELECT OSPath.Components AS Components
+                   FROM glob(globs=pattern, accessor="fs")
+                   WHERE NOT IsDir"#,
+                &QueryOptions::builder()
+                    .env(vec![(
+                        "pattern".into(),
+                        format!("/{}", pattern.trim_start_matches('/')),
+                    )])
+                    .build(),
+            )
+            .await?;
+        Ok(matches
+            .into_iter()
+            .map(|m| VfsPath::new(m.components))
+            .collect())
+    }
+
+    /// Download all files in the filestore matching a glob pattern such
+    /// as `downloads/C.XXXXXXXXXXXXXXXX/**/*.zip` into `dest_dir`,
+    /// preserving the directory layout. At most `concurrency` files are
+    /// downloaded at the same time. Returns the local paths of all
+    /// downloaded files.
+    pub async fn fetch_glob<P: AsRef<Path>>(
+        &self,
+        pattern: &str,
+        dest_dir: P,
+        concurrency: usize,
+    ) -> Result<Vec<PathBuf>, APIClientError> {
+        let matches = self.glob(pattern).await?;
+
+        let dest_dir = dest_dir.as_ref();
+        stream::iter(matches)
+            .map(|m| async move {
+                let local = m
+                    .components()
+                    .iter()
+                    .filter(|c| !matches!(c.as_str(), "" | "." | ".."))
+                    .fold(dest_dir.to_path_buf(), |p, c| {
+                        p.join(c.replace(['/', '\\'], "_"))
+                    });
+                if let Some(parent) = local.parent() {
+                    tokio::fs::create_dir_all(parent)
+                        .await
+                        .map_err(APIClientError::IO)?;
+                }
+                let mut output = tokio::fs::File::create(&local)
+                    .await
+                    .map_err(APIClientError::IO)?;
+                log::debug!("Fetching {} ...", local.to_string_lossy());
+                self.fetch_to(&m, &mut output).await?;
+                Ok(local)
+            })
+            .buffer_unordered(concurrency.max(1))
+            .try_collect()
+            .await
+    }
+
+    /// Upload data to the server's filestore, e.g. for tools or files
+    /// in the public directory. The data is read from `reader` and
+    /// sent in chunks. Returns the number of bytes written. Paths with
+    /// empty, `.` or `..` components are rejected.
+    pub async fn upload<P, R>(&self, path: P, reader: &mut R) -> Result<u64, APIClientError>
+    where
+        P: Into<VfsPath>,
+        R: AsyncRead + Unpin,
+    {
+        let path = path.into();
+        if !path.is_contained() {
+            return Err(APIClientError::IO(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid upload path: {path}"),
+            )));
+        }
+        let path = serde_json::to_string(path.components()).unwrap();
+        let mut buf = vec![0; UPLOAD_CHUNK_SIZE];
+        let mut written = 0;
+        loop {
+            let mut len = 0;
+            while len < buf.len() {
+                match reader
+                    .read(&mut buf[len..])
+                    .await
+                    .map_err(APIClientError::IO)?
+                {
+                    0 => break,
+                    n => len += n,
+                }
+            }
+            if len == 0 && written > 0 {
+                break;
+            }
+            self.sync_query::<serde_json::Value>(
+                r#"SELECT copy(filename=base64decode(string=data), accessor="data",
+                               dest=path_join(components=
+                                   (server_config.Datastore.filestore_directory, ) +
+                                   parse_json_array(data=path)),
+                               append=append, create_directories=TRUE)
+                   FROM scope()"#,
+                &QueryOptions::builder()
+                    .env(vec![
+                        ("path".into(), path.clone()),
+                        ("data".into(), BASE64_STANDARD.encode(&buf[..len])),
+                        ("append".into(), if written > 0 { "Y" } else { "" }.into()),
+                    ])
+                    .build(),
+            )
+            .await?;
+            written += len as u64;
+            if len < buf.len() {
+                break;
+            }
+        }
+        Ok(written)
+    }
+}
+
+/// Read `len` bytes starting at `offset` from a file in the filestore.
+/// Fails if the file ends before.
+async fn read_range(
+    client: &APIClient,
+    mut api_client: api_client::ApiClient<Channel>,
+    components: &[String],
+    offset: u64,
+    len: u64,
+) -> Result<Vec<u8>, APIClientError> {
+    let mut buf = Vec::with_capacity(len as usize);
+    while (buf.len() as u64) < len {
+        let start = std::time::Instant::now();
+        let response = api_client
+            .vfs_get_buffer(client.request(VfsFileBuffer {
+                components: components.to_vec(),
+                offset: offset + buf.len() as u64,
+                length: client.chunk_size.min((len - buf.len() as u64) as u32),
+                ..VfsFileBuffer::default()
+            }))
+            .await;
+        let response = client.rpc_result(response)?.into_inner();
+        crate::instrumentation::rpc_completed("VFSGetBuffer", start);
+        if response.data.is_empty() {
+            break;
+        }
+        buf.extend(response.data);
+    }
+    if (buf.len() as u64) < len {
+        return Err(APIClientError::IO(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!("expected {len} bytes at offset {offset}, got {}", buf.len()),
+        )));
+    }
+    Ok(buf)
+}
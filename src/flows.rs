@@ -0,0 +1,168 @@
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::row::null_as_default;
+use crate::{APIClientError, Client, ClientFlow, FlowLogEntry, QueryOptions, VfsPath, VrTimestamp};
+
+/// Flow columns selected from the `flows()` plugin
+const FLOW_COLUMNS: &str = r#"session_id AS flow_id, request.artifacts AS artifacts,
+    request.creator AS creator, state, status, create_time, active_time,
+    total_collected_rows, total_uploaded_bytes"#;
+
+/// Summary of a flow as listed by the `flows()` plugin
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FlowSummary {
+    pub flow_id: String,
+    #[serde(deserialize_with = "null_as_default")]
+    pub artifacts: Vec<String>,
+    /// User or hunt that created the flow
+    pub creator: String,
+    /// `RUNNING`, `FINISHED`, or `ERROR`
+    pub state: String,
+    /// Error message, if the flow failed
+    pub status: String,
+    pub create_time: VrTimestamp,
+    /// Time the client last reported progress
+    pub active_time: VrTimestamp,
+    pub total_collected_rows: u64,
+    pub total_uploaded_bytes: u64,
+}
+
+impl Client<'_> {
+    /// List the client's flows, including those started from the GUI
+    /// or by hunts, most recent first
+    pub async fn flows(&self) -> Result<Vec<FlowSummary>, APIClientError> {
+        let mut flows: Vec<FlowSummary> = self
+            .api_client
+            .sync_query(
+                &format!("SELECT {FLOW_COLUMNS} FROM flows(client_id=client_id)"),
+                &QueryOptions::builder()
+                    .env(vec![("client_id".into(), self.client_id.clone())])
+                    .build(),
+            )
+            .await?;
+        flows.sort_by_key(|f| std::cmp::Reverse(f.create_time));
+        Ok(flows)
+    }
+
+    /// Refer to an existing flow of the client by its ID
+    pub fn flow_unchecked(&self, flow_id: &str) -> ClientFlow<'_> {
+        ClientFlow {
+            api_client: self.api_client,
+            client_id: self.client_id.clone(),
+            flow_id: flow_id.to_string(),
+        }
+    }
+}
+
+impl ClientFlow<'_> {
+    /// Summary of the flow, if it is known to the server
+    pub async fn summary(&self) -> Result<Option<FlowSummary>, APIClientError> {
+        Ok(self
+            .api_client
+            .sync_query(
+                &format!("SELECT {FLOW_COLUMNS} FROM flows(client_id=client_id, flow_id=flow_id)"),
+                &self.query_options(),
+            )
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    /// Wait for the flow to finish, calling `progress` with the flow's
+    /// summary every time it is polled, as determined by the client's
+    /// [`PollStrategy`](crate::PollStrategy)
+    pub async fn wait_with_progress<F>(&self, mut progress: F) -> Result<(), APIClientError>
+    where
+        F: FnMut(&FlowSummary),
+    {
+        self.api_client
+            .bounded(&format!("waiting for flow {}", self.flow_id), async {
+                let mut attempt = 0;
+                loop {
+                    let summary = self.summary().await?.unwrap_or_default();
+                    progress(&summary);
+                    match summary.state.as_str() {
+                        "FINISHED" => return Ok(()),
+                        "ERROR" => return Err(APIClientError::FlowFailed(self.flow_id.clone())),
+                        _ => {
+                            self.api_client.poll.wait(attempt).await;
+                            attempt += 1;
+                        }
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Results collected by the flow so far. Unlike `fetch`, this
+    /// neither waits for the flow to finish nor for results to arrive.
+    pub async fn results<T: DeserializeOwned>(&self) -> Result<Vec<T>, APIClientError> {
+        self.api_client
+            .sync_query(
+                r#"SELECT * FROM flow_results(client_id=client_id, flow_id=flow_id)"#,
+                &self.query_options(),
+            )
+            .await
+    }
+
+    /// Like `results`, but only those of `artifact`. For artifacts
+    /// with several sources, a single source can be selected as
+    /// `Artifact/Source`.
+    pub async fn artifact_results<T: DeserializeOwned>(
+        &self,
+        artifact: &str,
+    ) -> Result<Vec<T>, APIClientError> {
+        self.api_client
+            .sync_query(
+                r#"SELECT * FROM flow_results(client_id=client_id, flow_id=flow_id,
+                                               artifact=artifact)"#,
+                &QueryOptions::builder()
+                    .env(vec![
+                        ("client_id".into(), self.client_id.clone()),
+                        ("flow_id".into(), self.flow_id.clone()),
+                        ("artifact".into(), artifact.to_string()),
+                    ])
+                    .build(),
+            )
+            .await
+    }
+
+    /// Log entries of the flow so far. Unlike `fetch_log`, this does
+    /// not wait for entries to arrive.
+    pub async fn log(&self) -> Result<Vec<FlowLogEntry>, APIClientError> {
+        self.api_client
+            .sync_query(
+                r#"SELECT * FROM flow_logs(client_id=client_id, flow_id=flow_id)"#,
+                &self.query_options(),
+            )
+            .await
+    }
+
+    /// Cancel the flow
+    pub async fn cancel(&self) -> Result<(), APIClientError> {
+        self.api_client
+            .sync_query::<serde_json::Value>(
+                r#"SELECT cancel_flow(client_id=client_id, flow_id=flow_id) FROM scope()"#,
+                &self.query_options(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Download all files uploaded by the flow into `dest_dir`,
+    /// fetching at most `concurrency` files at the same time. Returns
+    /// the local paths of the downloaded files.
+    pub async fn download_uploads<P: AsRef<Path>>(
+        &self,
+        dest_dir: P,
+        concurrency: usize,
+    ) -> Result<Vec<PathBuf>, APIClientError> {
+        let uploads = VfsPath::uploads(&self.client_id, &self.flow_id);
+        self.api_client
+            .fetch_glob(&format!("{uploads}/**"), dest_dir, concurrency)
+            .await
+    }
+}
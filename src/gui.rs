@@ -0,0 +1,40 @@
+use crate::{APIClient, Client, ClientFlow, Hunt};
+
+impl APIClient {
+    /// Build a GUI link to `route`, selecting the client's org if one
+    /// is set. `base` is the GUI's base URL, e.g.
+    /// `https://velociraptor.example/`.
+    fn gui_url(&self, base: &str, route: &str) -> String {
+        let base = base.trim_end_matches('/');
+        match self.org_id() {
+            Some(org_id) => format!("{base}/app/index.html?org_id={org_id}#/{route}"),
+            None => format!("{base}/app/index.html#/{route}"),
+        }
+    }
+}
+
+impl Client<'_> {
+    /// Link to the client's overview page in the GUI
+    pub fn gui_url(&self, base: &str) -> String {
+        self.api_client
+            .gui_url(base, &format!("host/{}", self.client_id))
+    }
+}
+
+impl ClientFlow<'_> {
+    /// Link to the flow's overview page in the GUI
+    pub fn gui_url(&self, base: &str) -> String {
+        self.api_client.gui_url(
+            base,
+            &format!("collected/{}/{}/overview", self.client_id, self.flow_id),
+        )
+    }
+}
+
+impl Hunt<'_> {
+    /// Link to the hunt's overview page in the GUI
+    pub fn gui_url(&self, base: &str) -> String {
+        self.api_client
+            .gui_url(base, &format!("hunts/{}/overview", self.hunt_id))
+    }
+}
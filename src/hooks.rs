@@ -0,0 +1,131 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use tokio::time::Duration;
+
+use crate::{APIClient, APIClientError};
+
+/// Connection states tracked to report transitions
+const UNKNOWN: u8 = 0;
+const UP: u8 = 1;
+const DOWN: u8 = 2;
+
+type ConnectHook = Arc<dyn Fn() + Send + Sync>;
+type DisconnectHook = Arc<dyn Fn(&dyn Error) + Send + Sync>;
+type RetryHook = Arc<dyn Fn(&str, u32, Duration) + Send + Sync>;
+
+/// Callbacks for connection lifecycle events. They are kept with the
+/// connection state in [`Shared`](crate::Shared), so that transitions
+/// observed by any clone of a client reach them.
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    on_connect: Option<ConnectHook>,
+    on_disconnect: Option<DisconnectHook>,
+    on_retry: Option<RetryHook>,
+}
+
+/// Check whether an RPC failed because the server could not be reached
+fn is_connection_error(status: &tonic::Status) -> bool {
+    status.code() == tonic::Code::Unavailable
+        || status
+            .source()
+            .is_some_and(|e| e.is::<tonic::transport::Error>())
+}
+
+impl APIClient {
+    /// Call `f` whenever a connection to the server has been
+    /// established, initially or after it was lost.
+    ///
+    /// Callbacks are registered for the connection, so they also apply
+    /// to clones of this client and to clients derived from it, e.g.
+    /// through [`with_org`](Self::with_org).
+    pub fn on_connect<F: Fn() + Send + Sync + 'static>(&self, f: F) -> &Self {
+        self.hooks_mut().on_connect = Some(Arc::new(f));
+        self
+    }
+
+    /// Call `f` with the error whenever the server cannot be reached
+    /// after it could be reached before, or when the first attempt to
+    /// connect fails
+    pub fn on_disconnect<F: Fn(&dyn Error) + Send + Sync + 'static>(&self, f: F) -> &Self {
+        self.hooks_mut().on_disconnect = Some(Arc::new(f));
+        self
+    }
+
+    /// Call `f` with the name of the operation, the number of the
+    /// retry, and the delay before it whenever an operation such as
+    /// [`follow_client_events`](Self::follow_client_events) retries
+    /// after losing the connection
+    pub fn on_retry<F: Fn(&str, u32, Duration) + Send + Sync + 'static>(&self, f: F) -> &Self {
+        self.hooks_mut().on_retry = Some(Arc::new(f));
+        self
+    }
+
+    fn hooks_mut(&self) -> std::sync::RwLockWriteGuard<'_, Hooks> {
+        self.shared.hooks.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Current callbacks. They are cloned so that no lock is held while
+    /// they run.
+    pub(crate) fn hooks(&self) -> Hooks {
+        self.shared
+            .hooks
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub(crate) fn connection_up(&self) {
+        if self.shared.state.swap(UP, Ordering::Relaxed) != UP {
+            log::debug!("connected to server");
+            if let Some(f) = &self.hooks().on_connect {
+                f();
+            }
+        }
+    }
+
+    pub(crate) fn connection_down(&self, error: &dyn Error) {
+        if self.shared.state.swap(DOWN, Ordering::Relaxed) != DOWN {
+            log::debug!("lost connection to server: {error}");
+            if let Some(f) = &self.hooks().on_disconnect {
+                f(error);
+            }
+        }
+    }
+
+    /// Convert the result of an RPC, keeping track of the connection
+    /// state
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn rpc_result<T>(
+        &self,
+        result: Result<T, tonic::Status>,
+    ) -> Result<T, APIClientError> {
+        match result {
+            Ok(response) => {
+                self.connection_up();
+                Ok(response)
+            }
+            Err(status) => {
+                if is_connection_error(&status) {
+                    self.connection_down(&status);
+                }
+                Err(APIClientError::Status(status))
+            }
+        }
+    }
+
+    /// Record that `operation` is retried for the `attempt`th time
+    /// after `delay`
+    pub(crate) fn retrying(&self, operation: &'static str, attempt: u32, delay: Duration) {
+        crate::instrumentation::retry(operation);
+        if let Some(f) = &self.hooks().on_retry {
+            f(operation, attempt, delay);
+        }
+    }
+}
+
+/// Initial connection state of a [`Shared`](crate::Shared)
+pub(crate) fn initial_state() -> AtomicU8 {
+    AtomicU8::new(UNKNOWN)
+}
@@ -0,0 +1,22 @@
+use crate::APIClient;
+
+/// Representation of a hunt
+pub struct Hunt<'a> {
+    pub(crate) api_client: &'a APIClient,
+    pub(crate) hunt_id: String,
+}
+
+impl std::fmt::Display for Hunt<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.hunt_id)
+    }
+}
+
+impl APIClient {
+    pub fn hunt_unchecked(&self, id: &str) -> Hunt<'_> {
+        Hunt {
+            api_client: self,
+            hunt_id: id.to_string(),
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Instrumentation through the [`metrics`](https://docs.rs/metrics)
+//! facade. Without the `metrics` feature, all functions are no-ops.
+//!
+//! The following metrics are recorded:
+//!
+//! - `velociraptor_api_queries_total`: VQL queries issued
+//! - `velociraptor_api_rows_received_total`: result rows received
+//! - `velociraptor_api_rpc_duration_seconds` (label `method`): RPC
+//!   latency, up to the first response for streaming RPCs
+//! - `velociraptor_api_retries_total` (label `operation`): reconnects
+//!   and other retries
+
+use std::time::Instant;
+
+#[cfg(feature = "metrics")]
+pub(crate) fn query_issued() {
+    metrics::counter!("velociraptor_api_queries_total").increment(1);
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn query_issued() {}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn rows_received(n: usize) {
+    metrics::counter!("velociraptor_api_rows_received_total").increment(n as u64);
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn rows_received(_n: usize) {}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn rpc_completed(method: &'static str, start: Instant) {
+    metrics::histogram!("velociraptor_api_rpc_duration_seconds", "method" => method)
+        .record(start.elapsed().as_secs_f64());
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn rpc_completed(_method: &'static str, _start: Instant) {}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn retry(operation: &'static str) {
+    metrics::counter!("velociraptor_api_retries_total", "operation" => operation).increment(1);
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn retry(_operation: &'static str) {}
@@ -1,12 +1,13 @@
-use std::path::{Component, Path};
+use std::path::Path;
+use std::sync::Arc;
 
 use serde::{de::DeserializeOwned, Deserialize};
 
-use tokio::time::{sleep, Duration};
-use tokio_stream::StreamExt;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use tokio::time::Duration;
 
 use tonic::{
-    transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity, Uri},
+    transport::{Channel, Endpoint},
     IntoRequest,
 };
 
@@ -15,7 +16,93 @@ use typed_builder::TypedBuilder;
 use thiserror::Error;
 
 use proto::*;
-mod proto;
+pub mod proto;
+
+mod artifact;
+pub use artifact::*;
+
+pub mod row;
+pub use row::VrTimestamp;
+pub use velociraptor_api_derive::ArtifactRow;
+// Lets the derive macro refer to this crate by name from within it
+extern crate self as velociraptor_api;
+
+mod filestore;
+pub use filestore::*;
+
+mod path;
+pub use path::VfsPath;
+
+mod vfs;
+pub use vfs::VfsEntry;
+
+mod clients;
+pub use clients::ClientSummary;
+
+mod flows;
+pub use flows::FlowSummary;
+
+mod waiter;
+pub use waiter::FlowWaiter;
+
+mod poll;
+pub use poll::{EventDriven, ExponentialBackoff, FixedInterval, PollStrategy};
+
+mod gui;
+
+mod instrumentation;
+
+mod maintenance;
+
+mod backup;
+
+mod events;
+pub use events::{AuditRecord, ClientLifecycleEvent, EventCursor, MonitoredArtifact};
+
+mod server;
+pub use server::*;
+
+mod completion;
+pub use completion::*;
+
+mod api;
+pub use api::VelociraptorApi;
+
+mod tls;
+
+mod hooks;
+
+mod builder;
+pub use builder::{APIClientBuilder, Compression, QueryLogging, RetryPolicy};
+
+mod hunt;
+pub use hunt::Hunt;
+
+mod notebook;
+pub use notebook::*;
+
+mod archive;
+pub use archive::{ArchiveOptions, EventArchiver};
+
+mod users;
+pub use users::*;
+
+mod orgs;
+pub use orgs::Org;
+
+mod secrets;
+pub use secrets::Secret;
+
+mod queue;
+pub use queue::ClientBacklog;
+
+pub mod alerts;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+
+pub mod builtin;
+#[cfg(feature = "test-util")]
+pub mod mock;
 
 /// Client configuration for the Velociraptor gRPC API
 #[allow(dead_code)]
@@ -26,6 +113,10 @@ pub struct APIClientConfig {
     client_private_key: String,
     api_connection_string: String,
     name: String,
+    /// Org used for all requests unless overridden through
+    /// `APIClient::with_org` or `QueryOptions`
+    #[serde(default)]
+    org_id: Option<String>,
 }
 
 #[derive(Debug, Error)]
@@ -34,6 +125,8 @@ pub enum ConfigError {
     IO(std::io::Error),
     #[error("Failed to parse YAML: {0}")]
     YAML(serde_yaml::Error),
+    #[error("Failed to parse certificate: {0}")]
+    Certificate(String),
 }
 
 #[derive(Debug, Error)]
@@ -48,6 +141,20 @@ pub enum APIClientError {
     MalformedResponse(serde_json::Error),
     #[error("Failed to run VQL query: {0}")]
     VQL(String),
+    #[error("I/O error: {0}")]
+    IO(std::io::Error),
+    #[error("Flow failed: {0}")]
+    FlowFailed(String),
+    #[error("Timed out: {0}")]
+    Timeout(String),
+    #[error(transparent)]
+    Integrity(IntegrityError),
+    #[error("Unknown artifact: {0}")]
+    UnknownArtifact(String),
+    #[error("Failed to parse artifact definition: {0}")]
+    MalformedArtifact(serde_yaml::Error),
+    #[error("Invalid parameters for artifact {0}: {}", ParameterError::join(.1))]
+    InvalidParameters(String, Vec<ParameterError>),
 }
 
 impl APIClientConfig {
@@ -59,34 +166,82 @@ impl APIClientConfig {
         Ok(cc)
     }
 
-    fn tls_config(&self) -> ClientTlsConfig {
-        let ca = Certificate::from_pem(self.ca_certificate.clone());
-        let id = Identity::from_pem(self.client_cert.clone(), self.client_private_key.clone());
-        ClientTlsConfig::new()
-            .domain_name("VelociraptorServer")
-            .ca_certificate(ca)
-            .identity(id)
+    /// Name of the API client principal
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Address of the server's API endpoint
+    pub fn api_connection_string(&self) -> &str {
+        &self.api_connection_string
+    }
+
+    /// Org used by default, if any
+    pub fn org_id(&self) -> Option<&str> {
+        self.org_id.as_deref().filter(|o| !o.is_empty())
+    }
+
+    /// Time after which the client certificate is no longer valid
+    pub fn certificate_expiry(&self) -> Result<time::OffsetDateTime, ConfigError> {
+        let (_, pem) = x509_parser::pem::parse_x509_pem(self.client_cert.as_bytes())
+            .map_err(|e| ConfigError::Certificate(e.to_string()))?;
+        let cert = pem
+            .parse_x509()
+            .map_err(|e| ConfigError::Certificate(e.to_string()))?;
+        Ok(cert.validity().not_after.to_datetime())
     }
 }
 
 /// APIClient for the Velociraptor gRPC API
+///
+/// The client is `Send + Sync` and cheap to clone: clones share the
+/// endpoint and its connection, so a single client can be stored in
+/// application state and used from many tasks at once.
+#[derive(Clone)]
 pub struct APIClient {
+    shared: Arc<Shared>,
+    org_id: Option<String>,
+    timeout: Option<Duration>,
+    chunk_size: u32,
+    retry: RetryPolicy,
+    compression: Compression,
+    poll: Arc<dyn PollStrategy>,
+    query_logging: QueryLogging,
+}
+
+/// Connection state shared between clones of an [`APIClient`]
+struct Shared {
     endpoint: Endpoint,
+    tls: tls::Connector,
+    /// Channel, established on first use
+    channel: tokio::sync::OnceCell<Channel>,
+    /// Whether the server could be reached by the last RPC
+    state: std::sync::atomic::AtomicU8,
+    /// Callbacks for changes of `state`
+    hooks: std::sync::RwLock<hooks::Hooks>,
 }
 
+impl Shared {
+    fn new(endpoint: Endpoint, tls: tls::Connector, hooks: hooks::Hooks) -> Arc<Self> {
+        Arc::new(Self {
+            endpoint,
+            tls,
+            channel: tokio::sync::OnceCell::new(),
+            state: hooks::initial_state(),
+            hooks: std::sync::RwLock::new(hooks),
+        })
+    }
+}
+
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<APIClient>();
+};
+
 impl TryFrom<&APIClientConfig> for APIClient {
     type Error = APIClientError;
     fn try_from(cfg: &APIClientConfig) -> Result<Self, Self::Error> {
-        let uri = Uri::builder()
-            .scheme("https")
-            .authority(cfg.api_connection_string.as_str())
-            .path_and_query("/")
-            .build()
-            .map_err(APIClientError::HTTP)?;
-        let endpoint = Endpoint::from(uri)
-            .tls_config(cfg.tls_config())
-            .map_err(APIClientError::Transport)?;
-        Ok(Self { endpoint })
+        Self::builder(cfg).build()
     }
 }
 
@@ -99,14 +254,125 @@ pub struct QueryOptions {
     /// Organization ID
     #[builder(default, setter(into))]
     org_id: Option<String>,
-    /// Maximum rows to return at a time
+    /// Maximum rows to return at a time, i.e. per response message
     #[builder(default)]
     max_row: u64,
 }
 
+/// Resource limits for a collection, enforced by the client
+#[derive(Clone, Debug, Default, TypedBuilder)]
+pub struct CollectLimits {
+    /// Maximum number of result rows
+    #[builder(default, setter(strip_option))]
+    pub max_rows: Option<u64>,
+    /// Maximum number of bytes to upload
+    #[builder(default, setter(strip_option))]
+    pub max_bytes: Option<u64>,
+    /// Maximum CPU usage, in percent
+    #[builder(default, setter(strip_option))]
+    pub cpu_limit: Option<u32>,
+    /// Time after which the collection is cancelled
+    #[builder(default, setter(strip_option))]
+    pub timeout: Option<Duration>,
+}
+
 impl APIClient {
     async fn api_client(&self) -> Result<api_client::ApiClient<Channel>, tonic::transport::Error> {
-        Ok(api_client::ApiClient::new(self.endpoint.connect().await?))
+        let channel = self
+            .shared
+            .channel
+            .get_or_try_init(|| self.shared.tls.connect(&self.shared.endpoint))
+            .await
+            .inspect_err(|e| self.connection_down(e))?;
+        let client = api_client::ApiClient::new(channel.clone());
+        Ok(match self.compression {
+            Compression::None => client,
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => client
+                .accept_compressed(tonic::codec::CompressionEncoding::Gzip)
+                .send_compressed(tonic::codec::CompressionEncoding::Gzip),
+        })
+    }
+
+    /// Connected low-level gRPC client, for calling RPCs such as
+    /// `GetClientMetadata` or `CreateHunt` that this crate does not
+    /// wrap yet. Use [`request`](Self::request) to build requests that
+    /// are scoped to the client's organization.
+    pub async fn raw(&self) -> Result<api_client::ApiClient<Channel>, APIClientError> {
+        self.api_client().await.map_err(APIClientError::Transport)
+    }
+
+    /// Return a client whose queries, flows, hunts, and filestore
+    /// operations are scoped to the given organization, unless an
+    /// organization is set explicitly in `QueryOptions`
+    pub fn with_org(&self, org_id: &str) -> APIClient {
+        APIClient {
+            org_id: Some(org_id.to_string()),
+            ..self.clone()
+        }
+    }
+
+    /// Organization this client is scoped to, if any
+    pub fn org_id(&self) -> Option<&str> {
+        self.org_id.as_deref()
+    }
+
+    /// Return a client whose RPCs, flow waits, and result fetches
+    /// fail with [`APIClientError::Timeout`] if they take longer than
+    /// `timeout`. The timeout is also passed to the server, so that
+    /// long-running queries are cancelled there. The returned client
+    /// uses a connection of its own, which starts out with the
+    /// callbacks registered for this client's connection.
+    pub fn with_timeout(&self, timeout: Duration) -> APIClient {
+        APIClient {
+            shared: Shared::new(
+                self.shared.endpoint.clone().timeout(timeout),
+                self.shared.tls.clone(),
+                self.hooks(),
+            ),
+            timeout: Some(timeout),
+            ..self.clone()
+        }
+    }
+
+    /// Return a client that waits for flows according to `strategy`
+    /// instead of polling at a fixed interval
+    pub fn with_poll_strategy<P: PollStrategy + 'static>(&self, strategy: P) -> APIClient {
+        APIClient {
+            poll: Arc::new(strategy),
+            ..self.clone()
+        }
+    }
+
+    /// Return a client that logs issued queries as determined by
+    /// `logging`
+    pub fn with_query_logging(&self, logging: QueryLogging) -> APIClient {
+        APIClient {
+            query_logging: logging,
+            ..self.clone()
+        }
+    }
+
+    /// Run a polling operation, bounded by the client's timeout
+    pub(crate) async fn bounded<T, F>(&self, what: &str, f: F) -> Result<T, APIClientError>
+    where
+        F: std::future::Future<Output = Result<T, APIClientError>>,
+    {
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, f)
+                .await
+                .map_err(|_| APIClientError::Timeout(what.to_string()))?,
+            None => f.await,
+        }
+    }
+
+    /// Wrap a request message, passing the organization ID as metadata
+    pub fn request<T>(&self, msg: T) -> tonic::Request<T> {
+        let mut request = msg.into_request();
+        if let Some(org_id) = self.org_id.as_ref().and_then(|o| o.parse().ok()) {
+            request.metadata_mut().insert("orgid", org_id);
+        }
+        request
     }
 
     /// Issue a server-side VQL query
@@ -115,101 +381,71 @@ impl APIClient {
         query: &str,
         options: &QueryOptions,
     ) -> Result<Vec<T>, APIClientError> {
+        self.query_stream(query, options).await?.try_collect().await
+    }
+
+    /// Issue a server-side VQL query, returning result rows as they
+    /// arrive.
+    ///
+    /// Responses are read from the connection only as the stream is
+    /// polled, so a slow consumer makes the server wait rather than
+    /// rows piling up in memory. At most one response message and the
+    /// buffer set by [`APIClientBuilder::stream_buffer`] are held per
+    /// query.
+    pub async fn query_stream<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        options: &QueryOptions,
+    ) -> Result<impl Stream<Item = Result<T, APIClientError>>, APIClientError> {
+        self.query_logging.log(query, &options.env);
         let env = options
             .env
             .iter()
             .cloned()
             .map(|(key, value)| VqlEnv { key, value })
             .collect::<Vec<_>>();
-        let org_id = options.org_id.clone().unwrap_or_default();
+        let org_id = options
+            .org_id
+            .clone()
+            .or_else(|| self.org_id.clone())
+            .unwrap_or_default();
         let query = vec![VqlRequest {
             name: "".into(),
             vql: query.into(),
         }];
         let max_row = options.max_row;
 
-        let mut response = self
+        instrumentation::query_issued();
+        let start = std::time::Instant::now();
+        let response = self
             .api_client()
             .await
             .map_err(APIClientError::Transport)?
-            .query(
-                VqlCollectorArgs {
-                    env,
-                    org_id,
-                    max_row,
-                    query,
-                    ..VqlCollectorArgs::default()
-                }
-                .into_request(),
-            )
-            .await
-            .map_err(APIClientError::Status)?
-            .into_inner();
-
-        let mut result = vec![];
-        while let Some(Ok(msg)) = response.next().await {
-            if !msg.response.is_empty() {
-                log::trace!("result = {}", &msg.response);
-                result.append(
-                    &mut serde_json::from_str(&msg.response)
-                        .map_err(APIClientError::MalformedResponse)?,
-                );
-            }
-            if !msg.log.is_empty() {
-                log::debug!("log = {}", msg.log.trim());
-                if msg.log.starts_with("VQL Error:") {
-                    return Err(APIClientError::VQL(msg.log));
-                }
-            }
-        }
-
-        Ok(result)
+            .query(self.request(VqlCollectorArgs {
+                env,
+                org_id,
+                max_row,
+                query,
+                ..VqlCollectorArgs::default()
+            }))
+            .await;
+        let response = self.rpc_result(response)?.into_inner();
+        instrumentation::rpc_completed("Query", start);
+
+        let client = self.clone();
+        Ok(response.flat_map(move |msg| {
+            stream::iter(parse_response(client.rpc_result(msg), client.query_logging))
+        }))
     }
 
     /// Fetch downloadable file from Velociraptor server
-    pub async fn fetch<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>, APIClientError> {
-        let components: Vec<_> = path
-            .as_ref()
-            .components()
-            .filter_map(|c| match c {
-                Component::Normal(s) => Some(s.to_string_lossy().to_string()),
-                _ => None,
-            })
-            .collect();
-
-        let request = VfsFileBuffer {
-            components,
-            length: 1024,
-            ..VfsFileBuffer::default()
-        };
-
-        let mut api_client = self.api_client().await.map_err(APIClientError::Transport)?;
-        let (mut buf, mut offset) = (vec![], 0);
-        loop {
-            let response = api_client
-                .vfs_get_buffer(
-                    VfsFileBuffer {
-                        offset,
-                        ..request.clone()
-                    }
-                    .into_request(),
-                )
-                .await
-                .map_err(APIClientError::Status)?
-                .into_inner();
-
-            match response.data.len() {
-                0 => break,
-                len => {
-                    buf.extend(response.data);
-                    offset += len as u64;
-                }
-            };
-        }
+    pub async fn fetch<P: Into<VfsPath>>(&self, path: P) -> Result<Vec<u8>, APIClientError> {
+        let mut buf = vec![];
+        self.fetch_to(path, &mut buf).await?;
         Ok(buf)
     }
 
-    pub fn new_client_unchecked(&self, id: &str) -> Client {
+    pub fn new_client_unchecked(&self, id: &str) -> Client<'_> {
         Client {
             api_client: self,
             client_id: id.to_string(),
@@ -217,6 +453,40 @@ impl APIClient {
     }
 }
 
+/// Split a single query response into result rows and errors. Rows
+/// are only logged verbatim if `logging` is [`QueryLogging::Full`],
+/// since they may echo values from the query environment.
+fn parse_response<T: DeserializeOwned>(
+    msg: Result<VqlResponse, APIClientError>,
+    logging: QueryLogging,
+) -> Vec<Result<T, APIClientError>> {
+    let msg = match msg {
+        Ok(msg) => msg,
+        Err(e) => return vec![Err(e)],
+    };
+    let mut result = vec![];
+    if !msg.response.is_empty() {
+        match logging {
+            QueryLogging::Full => log::trace!("result = {}", &msg.response),
+            _ => log::trace!("result: {} bytes", msg.response.len()),
+        }
+        match serde_json::from_str::<Vec<T>>(&msg.response) {
+            Ok(rows) => {
+                instrumentation::rows_received(rows.len());
+                result.extend(rows.into_iter().map(Ok))
+            }
+            Err(e) => result.push(Err(APIClientError::MalformedResponse(e))),
+        }
+    }
+    if !msg.log.is_empty() {
+        log::debug!("log = {}", msg.log.trim());
+        if msg.log.starts_with("VQL Error:") {
+            result.push(Err(APIClientError::VQL(msg.log)));
+        }
+    }
+    result
+}
+
 /// Representation of a Velociraptor client
 pub struct Client<'a> {
     api_client: &'a APIClient,
@@ -230,11 +500,39 @@ impl std::fmt::Display for Client<'_> {
 }
 
 impl Client<'_> {
+    /// Schedule collection of a shell artifact, passing `cmd` as its
+    /// `Command` parameter
     pub async fn schedule_flow(
         &self,
         artifact: &str,
         cmd: &str,
-    ) -> Result<ClientFlow, APIClientError> {
+    ) -> Result<ClientFlow<'_>, APIClientError> {
+        self.collect(artifact, &[("Command".to_string(), cmd.to_string())])
+            .await
+    }
+
+    /// Schedule collection of an artifact with the given parameters.
+    ///
+    /// Parameters are validated against the artifact definition before
+    /// the flow is scheduled.
+    pub async fn collect(
+        &self,
+        artifact: &str,
+        params: &[(String, String)],
+    ) -> Result<ClientFlow<'_>, APIClientError> {
+        self.collect_with_limits(artifact, params, &CollectLimits::default())
+            .await
+    }
+
+    /// Schedule collection of an artifact with the given parameters,
+    /// subject to resource limits. Limits that are not set default to
+    /// the server's defaults.
+    pub async fn collect_with_limits(
+        &self,
+        artifact: &str,
+        params: &[(String, String)],
+        limits: &CollectLimits,
+    ) -> Result<ClientFlow<'_>, APIClientError> {
         #[derive(Deserialize)]
         struct Request {
             flow_id: String,
@@ -244,10 +542,44 @@ impl Client<'_> {
             request: Request,
         }
 
+        self.api_client
+            .artifact(artifact)
+            .await?
+            .validate_parameters(params)
+            .map_err(|e| APIClientError::InvalidParameters(artifact.to_string(), e))?;
+
+        let params = params
+            .iter()
+            .cloned()
+            .map(|(k, v)| (k, serde_json::Value::String(v)))
+            .collect::<serde_json::Map<_, _>>();
         let env = vec![
             ("client_id".to_string(), self.client_id.to_string()),
             ("artifact".to_string(), artifact.to_string()),
-            ("Command".to_string(), cmd.to_string()),
+            (
+                "params".to_string(),
+                serde_json::to_string(&params).unwrap(),
+            ),
+            (
+                "max_rows".to_string(),
+                limits.max_rows.unwrap_or_default().to_string(),
+            ),
+            (
+                "max_bytes".to_string(),
+                limits.max_bytes.unwrap_or_default().to_string(),
+            ),
+            (
+                "cpu_limit".to_string(),
+                limits.cpu_limit.unwrap_or_default().to_string(),
+            ),
+            (
+                "timeout".to_string(),
+                limits
+                    .timeout
+                    .map(|t| t.as_secs())
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
         ];
         let requests: Vec<Submit> = self
             .api_client
@@ -255,13 +587,14 @@ impl Client<'_> {
                 r#"SELECT
                    collect_client(client_id=client_id,
                                   artifacts=artifact,
-                                  env=dict(Command=Command))
+                                  env=parse_json(data=params),
+                                  max_rows=int(int=max_rows),
+                                  max_bytes=int(int=max_bytes),
+                                  cpu_limit=int(int=cpu_limit),
+                                  timeout=int(int=timeout))
                    AS request
                    FROM scope()"#,
-                &QueryOptions::builder()
-                    .env(env.as_slice())
-                    .org_id("".to_string())
-                    .build(),
+                &QueryOptions::builder().env(env.as_slice()).build(),
             )
             .await?;
 
@@ -275,9 +608,9 @@ impl Client<'_> {
 
 /// Representation of a flow scheduled to executed by a Velociraptor client.
 pub struct ClientFlow<'a> {
-    api_client: &'a APIClient,
-    client_id: String,
-    flow_id: String,
+    pub(crate) api_client: &'a APIClient,
+    pub(crate) client_id: String,
+    pub(crate) flow_id: String,
 }
 
 impl std::fmt::Display for ClientFlow<'_> {
@@ -287,100 +620,73 @@ impl std::fmt::Display for ClientFlow<'_> {
 }
 
 impl ClientFlow<'_> {
-    pub async fn fetch<T: DeserializeOwned>(&self) -> Result<Vec<T>, APIClientError> {
-        #[derive(Clone, Default, Deserialize)]
-        struct FlowStatus {
-            state: String, // UNSET, RUNNING, FINISHED, ERROR
-        }
-
-        let options = QueryOptions::builder()
+    fn query_options(&self) -> QueryOptions {
+        QueryOptions::builder()
             .env(vec![
                 ("client_id".into(), self.client_id.clone()),
                 ("flow_id".into(), self.flow_id.clone()),
             ])
-            .org_id("".to_string())
-            .build();
-
-        loop {
-            log::debug!("Looking for {} / {} ...", self.client_id, self.flow_id);
-            let status = self
-                .api_client
-                .sync_query::<FlowStatus>(
-                    r#"SELECT * FROM flows(client_id=client_id, flow_id=flow_id)"#,
-                    &options,
-                )
-                .await?;
-            let state = status.first().cloned().unwrap_or_default().state;
-            log::debug!("state( {} / {} ): {state}", &self.client_id, &self.flow_id);
-            if state != "RUNNING" {
-                break;
-            }
-            sleep(Duration::from_millis(100)).await;
-        }
+            .build()
+    }
 
-        log::debug!(
-            "Fetching result for {} / {} ...",
-            self.client_id,
-            self.flow_id
-        );
-        loop {
-            let result = self
-                .api_client
-                .sync_query::<T>(
-                    r#"SELECT * FROM flow_results(client_id=client_id, flow_id=flow_id)"#,
-                    &options,
-                )
-                .await?;
-            if !result.is_empty() {
-                log::debug!("Done!");
-                return Ok(result);
-            }
-            log::trace!("zZz...");
-            sleep(Duration::from_millis(100)).await;
+    /// Current state of the flow: `RUNNING`, `FINISHED`, `ERROR`, or
+    /// an empty string if the flow is not (yet) known to the server
+    pub async fn state(&self) -> Result<String, APIClientError> {
+        #[derive(Clone, Default, Deserialize)]
+        struct FlowStatus {
+            state: String, // UNSET, RUNNING, FINISHED, ERROR
         }
+
+        log::debug!("Looking for {} / {} ...", self.client_id, self.flow_id);
+        let status = self
+            .api_client
+            .sync_query::<FlowStatus>(
+                r#"SELECT * FROM flows(client_id=client_id, flow_id=flow_id)"#,
+                &self.query_options(),
+            )
+            .await?;
+        let state = status.first().cloned().unwrap_or_default().state;
+        log::debug!("state( {} / {} ): {state}", &self.client_id, &self.flow_id);
+        Ok(state)
+    }
+
+    /// Wait for the flow to finish
+    pub async fn wait(&self) -> Result<(), APIClientError> {
+        self.api_client
+            .bounded(
+                &format!("waiting for flow {}", self.flow_id),
+                self.waiter().finished(),
+            )
+            .await
+    }
+
+    /// Wait for the flow's results and return them. See
+    /// [`FlowWaiter`] for a cancellation-safe alternative.
+    pub async fn fetch<T: DeserializeOwned>(&self) -> Result<Vec<T>, APIClientError> {
+        self.api_client
+            .bounded(
+                &format!("fetching results of flow {}", self.flow_id),
+                self.waiter().results(),
+            )
+            .await
     }
 
+    /// Wait for the flow's log and return it. See [`FlowWaiter`] for
+    /// a cancellation-safe alternative.
     pub async fn fetch_log(&self) -> Result<Vec<FlowLogEntry>, APIClientError> {
-        let options = QueryOptions::builder()
-            .env(vec![
-                ("client_id".into(), self.client_id.clone()),
-                ("flow_id".into(), self.flow_id.clone()),
-            ])
-            .org_id("".to_string())
-            .build();
-        let mut result: Vec<FlowLogEntry>;
-        loop {
-            result = self
-                .api_client
-                .sync_query(
-                    r#"SELECT * FROM flow_logs(client_id=client_id, flow_id=flow_id)"#,
-                    &options,
-                )
-                .await?;
-            if result.is_empty() {
-                sleep(Duration::from_millis(100)).await;
-                log::debug!("Retrying...");
-            } else {
-                for r in &result {
-                    log::debug!(
-                        "flow_log({}/{}): {} {}: {}",
-                        self.client_id,
-                        self.flow_id,
-                        r.client_time,
-                        r.level,
-                        r.message
-                    );
-                }
-                return Ok(result);
-            }
-        }
+        self.api_client
+            .bounded(
+                &format!("fetching log of flow {}", self.flow_id),
+                self.waiter().log(),
+            )
+            .await
     }
 }
 
 /// A single flow log entry
 #[derive(Deserialize)]
 pub struct FlowLogEntry {
-    pub client_time: u64,
+    pub client_time: VrTimestamp,
     pub level: String,
     pub message: String,
 }
@@ -0,0 +1,100 @@
+use time::OffsetDateTime;
+
+use crate::{APIClient, APIClientError, QueryOptions};
+
+impl APIClient {
+    /// Run a server maintenance artifact synchronously, returning the
+    /// rows it reports
+    async fn run_maintenance(
+        &self,
+        artifact: &str,
+        params: &[(&str, String)],
+    ) -> Result<Vec<serde_json::Value>, APIClientError> {
+        let args = params
+            .iter()
+            .map(|(k, _)| format!("{k}={k}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.sync_query(
+            &format!("SELECT * FROM Artifact.{artifact}({args})"),
+            &QueryOptions::builder()
+                .env(
+                    params
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.clone()))
+                        .collect::<Vec<_>>(),
+                )
+                .build(),
+        )
+        .await
+    }
+
+    /// Delete stored rows of event artifacts whose names match
+    /// `artifact_regex` that were recorded before `before`, using
+    /// `Server.Utils.DeleteMonitoringData`. Unless `really_do_it` is
+    /// set, the files that would be removed are only reported.
+    pub async fn expire_monitoring_data(
+        &self,
+        artifact_regex: &str,
+        before: OffsetDateTime,
+        really_do_it: bool,
+    ) -> Result<Vec<serde_json::Value>, APIClientError> {
+        self.run_maintenance(
+            "Server.Utils.DeleteMonitoringData",
+            &[
+                ("ArtifactRegex", artifact_regex.to_string()),
+                ("DateBefore", before.unix_timestamp().to_string()),
+                ("ReallyDoIt", if really_do_it { "Y" } else { "N" }.into()),
+            ],
+        )
+        .await
+    }
+
+    /// Delete flows of artifacts matching `artifact_regex` that were
+    /// created before `before`, using `Server.Utils.DeleteManyFlows`.
+    /// Unless `really_do_it` is set, the flows that would be removed
+    /// are only reported.
+    pub async fn expire_flows(
+        &self,
+        artifact_regex: &str,
+        before: OffsetDateTime,
+        really_do_it: bool,
+    ) -> Result<Vec<serde_json::Value>, APIClientError> {
+        self.run_maintenance(
+            "Server.Utils.DeleteManyFlows",
+            &[
+                ("ArtifactRegex", artifact_regex.to_string()),
+                ("DateBefore", before.unix_timestamp().to_string()),
+                ("ReallyDoIt", if really_do_it { "Y" } else { "N" }.into()),
+            ],
+        )
+        .await
+    }
+
+    /// Remove clients that have not been seen since `before`, together
+    /// with all their data, and drop them from the client index.
+    /// Unless `really_do_it` is set, the clients are only reported.
+    pub async fn expire_clients(
+        &self,
+        before: OffsetDateTime,
+        really_do_it: bool,
+    ) -> Result<Vec<serde_json::Value>, APIClientError> {
+        self.sync_query(
+            r#"SELECT * FROM foreach(
+                 row={ SELECT client_id FROM clients()
+                       WHERE last_seen_at / 1000000 < int(int=before) },
+                 query={ SELECT * FROM client_delete(client_id=client_id,
+                                                     really_do_it=really_do_it) })"#,
+            &QueryOptions::builder()
+                .env(vec![
+                    ("before".into(), before.unix_timestamp().to_string()),
+                    (
+                        "really_do_it".into(),
+                        if really_do_it { "Y" } else { "" }.into(),
+                    ),
+                ])
+                .build(),
+        )
+        .await
+    }
+}
@@ -0,0 +1,334 @@
+//! In-process fake of the Velociraptor API server, for tests that
+//! should not depend on a live server.
+//!
+//! [`MockServer`] answers VQL queries with canned rows, plays back
+//! scripted state transitions for flows, and serves and stores files
+//! in an in-memory filestore. Queries and written events are recorded
+//! so that tests can make assertions about them.
+//!
+//! The server speaks plain-text gRPC on a local port; clients
+//! returned by [`MockServer::client`] connect to it without TLS.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use base64::prelude::*;
+use futures::stream::{self, Stream};
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+use tonic::transport::{Endpoint, Server};
+use tonic::{Request, Response, Status};
+
+use crate::proto::api_server::{Api, ApiServer};
+use crate::proto::{VfsFileBuffer, VqlCollectorArgs, VqlResponse};
+use crate::{APIClient, Shared, VfsPath};
+
+/// A flow whose state is scripted by the test
+struct Flow {
+    /// States reported by successive `flows()` queries. The last one
+    /// is repeated once the others have been reported.
+    states: VecDeque<String>,
+    results: Vec<Value>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Rows returned for queries containing the given text
+    responses: Vec<(String, Vec<Value>)>,
+    /// Raw definitions of known artifacts, by name
+    artifacts: HashMap<String, String>,
+    flows: HashMap<String, Flow>,
+    /// Flows handed out by the next `collect_client()` calls
+    pending: VecDeque<String>,
+    files: HashMap<Vec<String>, Vec<u8>>,
+    queries: Vec<String>,
+    events: Vec<(String, Value)>,
+}
+
+impl State {
+    fn answer(&mut self, query: &str, env: &HashMap<String, String>) -> Vec<Value> {
+        let var = |name: &str| env.get(name).cloned().unwrap_or_default();
+        if let Some((_, rows)) = self
+            .responses
+            .iter()
+            .rev()
+            .find(|(needle, _)| query.contains(needle.as_str()))
+        {
+            return rows.clone();
+        }
+        if query.contains("artifact_definitions(names=") {
+            return self
+                .artifacts
+                .get(&var("name"))
+                .map(|raw| vec![json!({ "raw": raw })])
+                .unwrap_or_default();
+        }
+        if query.contains("collect_client(") {
+            return match self.pending.pop_front() {
+                Some(flow_id) => vec![json!({ "request": { "flow_id": flow_id } })],
+                None => vec![],
+            };
+        }
+        if query.contains("FROM flows(") {
+            return match self.flows.get_mut(&var("flow_id")) {
+                Some(flow) => {
+                    let state = if flow.states.len() > 1 {
+                        flow.states.pop_front()
+                    } else {
+                        flow.states.front().cloned()
+                    };
+                    vec![json!({
+                        "state": state.unwrap_or_default(),
+                        "total_collected_rows": flow.results.len(),
+                    })]
+                }
+                None => vec![],
+            };
+        }
+        if query.contains("FROM stat(") {
+            let path = var("path");
+            return self
+                .files
+                .iter()
+                .find(|(components, _)| VfsPath::new(components.iter()).to_vql_path() == path)
+                .map(|(_, data)| vec![json!({ "Size": data.len(), "Mtime": null })])
+                .unwrap_or_default();
+        }
+        if query.contains(r#"FROM glob(globs="*", root=path"#) {
+            let root = var("path");
+            let mut entries: Vec<(String, Value)> = vec![];
+            for (components, data) in &self.files {
+                let Some((name, parent)) = (1..=components.len())
+                    .rev()
+                    .map(|n| (&components[n - 1], &components[..n - 1]))
+                    .find(|(_, parent)| VfsPath::new(parent.iter()).to_vql_path() == root)
+                else {
+                    continue;
+                };
+                let is_dir = parent.len() + 1 < components.len();
+                if entries.iter().any(|(n, _)| n == name) {
+                    continue;
+                }
+                let path = VfsPath::new(parent.iter().chain([name]));
+                entries.push((
+                    name.clone(),
+                    json!({
+                        "Name": name,
+                        "OSPath": path.to_vql_path(),
+                        "Size": if is_dir { 0 } else { data.len() },
+                        "Mtime": null,
+                        "IsDir": is_dir,
+                    }),
+                ));
+            }
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            return entries.into_iter().map(|(_, entry)| entry).collect();
+        }
+        if query.contains("SELECT copy(") {
+            let components: Vec<String> = serde_json::from_str(&var("path")).unwrap_or_default();
+            let data = BASE64_STANDARD.decode(var("data")).unwrap_or_default();
+            let file = self.files.entry(components).or_default();
+            if var("append").is_empty() {
+                file.clear();
+            }
+            file.extend(data);
+            return vec![json!({ "copy": var("path") })];
+        }
+        if query.contains("FROM flow_results(") {
+            return self
+                .flows
+                .get(&var("flow_id"))
+                .map(|flow| flow.results.clone())
+                .unwrap_or_default();
+        }
+        vec![]
+    }
+}
+
+struct Service(Arc<Mutex<State>>);
+
+#[tonic::async_trait]
+impl Api for Service {
+    type QueryStream = Pin<Box<dyn Stream<Item = Result<VqlResponse, Status>> + Send>>;
+
+    async fn query(
+        &self,
+        request: Request<VqlCollectorArgs>,
+    ) -> Result<Response<Self::QueryStream>, Status> {
+        let args = request.into_inner();
+        let env: HashMap<_, _> = args.env.into_iter().map(|e| (e.key, e.value)).collect();
+        let mut state = self.0.lock().unwrap();
+        let responses: Vec<_> = args
+            .query
+            .into_iter()
+            .map(|q| {
+                state.queries.push(q.vql.clone());
+                let rows = state.answer(&q.vql, &env);
+                VqlResponse {
+                    response: Value::from(rows).to_string(),
+                    query: Some(q),
+                    ..VqlResponse::default()
+                }
+            })
+            .collect();
+        Ok(Response::new(Box::pin(stream::iter(
+            responses.into_iter().map(Ok),
+        ))))
+    }
+
+    async fn vfs_get_buffer(
+        &self,
+        request: Request<VfsFileBuffer>,
+    ) -> Result<Response<VfsFileBuffer>, Status> {
+        let mut buffer = request.into_inner();
+        let state = self.0.lock().unwrap();
+        let data = state
+            .files
+            .get(&buffer.components)
+            .ok_or_else(|| Status::not_found(buffer.components.join("/")))?;
+        let start = (buffer.offset as usize).min(data.len());
+        let end = (start + buffer.length as usize).min(data.len());
+        buffer.data = data[start..end].to_vec();
+        Ok(Response::new(buffer))
+    }
+
+    async fn write_event(&self, request: Request<VqlResponse>) -> Result<Response<()>, Status> {
+        let event = request.into_inner();
+        let artifact = event.query.map(|q| q.name).unwrap_or_default();
+        let rows: Vec<Value> = serde_json::from_str(&event.response)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let mut state = self.0.lock().unwrap();
+        state
+            .events
+            .extend(rows.into_iter().map(|row| (artifact.clone(), row)));
+        Ok(Response::new(()))
+    }
+}
+
+/// Fake API server running on a local port until it is dropped
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl MockServer {
+    /// Start a server on a free port of the loopback interface
+    pub async fn start() -> std::io::Result<Self> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State::default()));
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        let incoming = async_stream::stream! {
+            loop {
+                yield listener.accept().await.map(|(stream, _)| stream);
+            }
+        };
+        let service = ApiServer::new(Service(state.clone()));
+        #[cfg(feature = "gzip")]
+        let service = service
+            .accept_compressed(tonic::codec::CompressionEncoding::Gzip)
+            .send_compressed(tonic::codec::CompressionEncoding::Gzip);
+        tokio::spawn(async move {
+            let _ = Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(incoming, async {
+                    let _ = stopped.await;
+                })
+                .await;
+        });
+        Ok(Self {
+            addr,
+            state,
+            _shutdown: shutdown,
+        })
+    }
+
+    /// Address the server listens on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Client connected to this server
+    pub fn client(&self) -> APIClient {
+        let endpoint = Endpoint::from_shared(format!("http://{}", self.addr))
+            .expect("socket address is a valid URI");
+        APIClient {
+            shared: Shared::new(endpoint, Default::default(), Default::default()),
+            org_id: None,
+            timeout: None,
+            chunk_size: crate::builder::DEFAULT_CHUNK_SIZE,
+            retry: Default::default(),
+            compression: Default::default(),
+            poll: Arc::new(crate::FixedInterval::default()),
+            query_logging: Default::default(),
+        }
+    }
+
+    /// Answer queries containing `needle` with `rows`. Responses added
+    /// later take precedence over earlier ones, and over the server's
+    /// built-in handling of artifacts, flows, and results.
+    pub fn on_query(&self, needle: &str, rows: Vec<Value>) -> &Self {
+        let mut state = self.state.lock().unwrap();
+        state.responses.push((needle.to_string(), rows));
+        self
+    }
+
+    /// Make an artifact definition known to the server, so that
+    /// collections can validate their parameters against it
+    pub fn add_artifact(&self, yaml: &str) -> &Self {
+        let definition: serde_yaml::Value =
+            serde_yaml::from_str(yaml).expect("artifact definition is valid YAML");
+        let name = definition["name"]
+            .as_str()
+            .expect("artifact definition has a name")
+            .to_string();
+        let mut state = self.state.lock().unwrap();
+        state.artifacts.insert(name, yaml.to_string());
+        self
+    }
+
+    /// Script the next collection: it is assigned `flow_id`, reports
+    /// `states` (e.g. `RUNNING`, `FINISHED`) one after another when
+    /// polled, and returns `results`.
+    pub fn expect_collection(&self, flow_id: &str, states: &[&str], results: Vec<Value>) -> &Self {
+        let mut state = self.state.lock().unwrap();
+        state.pending.push_back(flow_id.to_string());
+        state.flows.insert(
+            flow_id.to_string(),
+            Flow {
+                states: states.iter().map(|s| s.to_string()).collect(),
+                results,
+            },
+        );
+        self
+    }
+
+    /// Add a file to the filestore
+    pub fn add_file<P: Into<VfsPath>>(&self, path: P, data: &[u8]) -> &Self {
+        let mut state = self.state.lock().unwrap();
+        state
+            .files
+            .insert(path.into().components().to_vec(), data.to_vec());
+        self
+    }
+
+    /// Contents of a file in the filestore, e.g. one written by
+    /// [`APIClient::upload`]
+    pub fn file<P: Into<VfsPath>>(&self, path: P) -> Option<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        state.files.get(path.into().components()).cloned()
+    }
+
+    /// VQL of all queries received so far
+    pub fn queries(&self) -> Vec<String> {
+        self.state.lock().unwrap().queries.clone()
+    }
+
+    /// Artifact names and rows of all events written so far
+    pub fn events(&self) -> Vec<(String, Value)> {
+        self.state.lock().unwrap().events.clone()
+    }
+}
@@ -0,0 +1,482 @@
+use serde::{Deserialize, Serialize};
+
+use typed_builder::TypedBuilder;
+
+use tokio::io::AsyncRead;
+
+use crate::{
+    APIClient, APIClientError, ClientFlow, FilestoreEntry, Hunt, QueryOptions, VfsPath, VrTimestamp,
+};
+
+/// Type of a notebook cell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellType {
+    Markdown,
+    Vql,
+}
+
+impl CellType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Markdown => "markdown",
+            Self::Vql => "vql",
+        }
+    }
+}
+
+/// Format of a notebook export
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Self-contained HTML report
+    Html,
+    /// Zip archive containing the notebook and all its data
+    Zip,
+}
+
+impl ExportFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Zip => "zip",
+        }
+    }
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "html" => Ok(Self::Html),
+            "zip" => Ok(Self::Zip),
+            _ => Err(format!("unknown export format: {s}")),
+        }
+    }
+}
+
+/// Content of a notebook cell
+#[derive(Clone, Debug)]
+pub struct NotebookCell {
+    pub kind: CellType,
+    pub input: String,
+}
+
+impl NotebookCell {
+    pub fn markdown(input: &str) -> Self {
+        Self {
+            kind: CellType::Markdown,
+            input: input.to_string(),
+        }
+    }
+
+    pub fn vql(input: &str) -> Self {
+        Self {
+            kind: CellType::Vql,
+            input: input.to_string(),
+        }
+    }
+}
+
+/// Cell as listed in a notebook's metadata
+#[derive(Deserialize)]
+struct CellMetadata {
+    cell_id: String,
+    #[serde(default)]
+    input: Option<String>,
+    #[serde(rename = "type", default)]
+    kind: String,
+}
+
+/// Summary of a notebook as returned by the `notebooks()` plugin
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NotebookSummary {
+    pub notebook_id: String,
+    pub name: String,
+    pub description: String,
+    pub creator: String,
+    /// Creation time
+    pub created_time: VrTimestamp,
+    /// Last modification time
+    pub modified_time: VrTimestamp,
+    /// Users the notebook is shared with
+    #[serde(deserialize_with = "crate::row::null_as_default")]
+    pub collaborators: Vec<String>,
+    pub public: bool,
+}
+
+impl NotebookSummary {
+    /// ID of the hunt the notebook is attached to, if any
+    pub fn hunt_id(&self) -> Option<&str> {
+        self.notebook_id
+            .strip_prefix("N.")
+            .filter(|id| id.starts_with("H."))
+    }
+
+    /// ID of the flow the notebook is attached to, if any
+    pub fn flow_id(&self) -> Option<&str> {
+        self.notebook_id
+            .strip_prefix("N.")
+            .filter(|id| id.starts_with("F."))
+            .map(|id| id.split('-').next().unwrap_or(id))
+    }
+}
+
+/// Filters for the `notebooks` method
+#[derive(Clone, Debug, Default, TypedBuilder)]
+pub struct NotebookFilter {
+    /// Only notebooks created by this user
+    #[builder(default, setter(strip_option, into))]
+    pub creator: Option<String>,
+    /// Only notebooks shared with this user
+    #[builder(default, setter(strip_option, into))]
+    pub shared_with: Option<String>,
+    /// Only notebooks attached to this hunt
+    #[builder(default, setter(strip_option, into))]
+    pub hunt_id: Option<String>,
+    /// Only notebooks attached to this flow
+    #[builder(default, setter(strip_option, into))]
+    pub flow_id: Option<String>,
+}
+
+impl NotebookFilter {
+    fn matches(&self, n: &NotebookSummary) -> bool {
+        self.creator.as_ref().is_none_or(|c| c == &n.creator)
+            && self
+                .shared_with
+                .as_ref()
+                .is_none_or(|u| n.collaborators.contains(u))
+            && self
+                .hunt_id
+                .as_ref()
+                .is_none_or(|h| n.hunt_id() == Some(h.as_str()))
+            && self
+                .flow_id
+                .as_ref()
+                .is_none_or(|f| n.flow_id() == Some(f.as_str()))
+    }
+}
+
+/// Representation of a notebook on the server
+pub struct Notebook<'a> {
+    api_client: &'a APIClient,
+    notebook_id: String,
+}
+
+impl std::fmt::Display for Notebook<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.notebook_id)
+    }
+}
+
+impl APIClient {
+    /// Create a notebook with the given cells
+    pub async fn create_notebook(
+        &self,
+        title: &str,
+        cells: &[NotebookCell],
+    ) -> Result<Notebook<'_>, APIClientError> {
+        #[derive(Deserialize)]
+        struct Created {
+            notebook_id: String,
+        }
+        #[derive(Deserialize)]
+        struct Row {
+            notebook: Option<Created>,
+        }
+        let notebook_id = self
+            .sync_query::<Row>(
+                r#"SELECT notebook_create(name=title) AS notebook FROM scope()"#,
+                &QueryOptions::builder()
+                    .env(vec![("title".into(), title.into())])
+                    .build(),
+            )
+            .await?
+            .into_iter()
+            .next()
+            .and_then(|r| r.notebook)
+            .ok_or_else(|| APIClientError::VQL("notebook_create() failed".into()))?
+            .notebook_id;
+        let notebook = self.notebook_unchecked(&notebook_id);
+        for cell in cells {
+            notebook.add_cell(cell).await?;
+        }
+        Ok(notebook)
+    }
+
+    /// Create a notebook from an artifact's notebook templates, as the
+    /// GUI does when a collection of the artifact is viewed.
+    ///
+    /// Sources that define `notebook` cells contribute those cells;
+    /// other sources contribute a VQL cell selecting their results.
+    /// The artifact's first report matching its type is added as a
+    /// Markdown cell, which the server renders as a template.
+    pub async fn create_notebook_from_artifact(
+        &self,
+        artifact: &str,
+        title: &str,
+    ) -> Result<Notebook<'_>, APIClientError> {
+        let def = self.artifact(artifact).await?;
+        let mut cells = vec![];
+        for source in &def.sources {
+            let source_name = match source.name.as_str() {
+                "" => def.name.clone(),
+                name => format!("{}/{name}", def.name),
+            };
+            if source.notebook.is_empty() {
+                cells.push(NotebookCell::vql(&format!(
+                    "SELECT * FROM source(artifact={source_name:?})"
+                )));
+            }
+            for template in &source.notebook {
+                cells.push(match template.kind.to_lowercase().as_str() {
+                    "vql" => NotebookCell::vql(&template.template),
+                    _ => NotebookCell::markdown(&template.template),
+                });
+            }
+        }
+        if let Some(report) = def
+            .reports
+            .iter()
+            .find(|r| r.kind.eq_ignore_ascii_case(def.kind.as_str()))
+        {
+            cells.push(NotebookCell::markdown(&report.template));
+        }
+        self.create_notebook(title, &cells).await
+    }
+
+    /// List notebooks matching the given filter
+    pub async fn notebooks(
+        &self,
+        filter: &NotebookFilter,
+    ) -> Result<Vec<NotebookSummary>, APIClientError> {
+        Ok(self
+            .sync_query::<NotebookSummary>(
+                r#"SELECT * FROM notebooks()"#,
+                &QueryOptions::builder().build(),
+            )
+            .await?
+            .into_iter()
+            .filter(|n| filter.matches(n))
+            .collect())
+    }
+
+    /// Retrieve the notebook with the given ID, creating it if it does
+    /// not exist yet
+    async fn notebook_get_or_create(
+        &self,
+        notebook_id: &str,
+        title: &str,
+    ) -> Result<Notebook<'_>, APIClientError> {
+        #[derive(Deserialize)]
+        struct Row {
+            notebook: Option<serde_json::Value>,
+        }
+        self.sync_query::<Row>(
+            r#"SELECT notebook_get(notebook_id=notebook_id) ||
+                      notebook_create(notebook_id=notebook_id, name=title) AS notebook
+               FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![
+                    ("notebook_id".into(), notebook_id.into()),
+                    ("title".into(), title.into()),
+                ])
+                .build(),
+        )
+        .await?
+        .into_iter()
+        .next()
+        .and_then(|r| r.notebook)
+        .ok_or_else(|| APIClientError::VQL("notebook_create() failed".into()))?;
+        Ok(self.notebook_unchecked(notebook_id))
+    }
+
+    pub fn notebook_unchecked(&self, id: &str) -> Notebook<'_> {
+        Notebook {
+            api_client: self,
+            notebook_id: id.to_string(),
+        }
+    }
+}
+
+impl Notebook<'_> {
+    pub fn id(&self) -> &str {
+        &self.notebook_id
+    }
+
+    fn query_options(&self, mut env: Vec<(String, String)>) -> QueryOptions {
+        env.push(("notebook_id".into(), self.notebook_id.clone()));
+        QueryOptions::builder().env(env).build()
+    }
+
+    /// Append a cell to the notebook, returning its ID
+    pub async fn add_cell(&self, cell: &NotebookCell) -> Result<String, APIClientError> {
+        self.update_cell("", cell).await
+    }
+
+    /// Replace the content of a cell and recalculate it. An empty
+    /// `cell_id` adds a new cell. Returns the cell's ID.
+    pub async fn update_cell(
+        &self,
+        cell_id: &str,
+        cell: &NotebookCell,
+    ) -> Result<String, APIClientError> {
+        #[derive(Deserialize)]
+        struct Cell {
+            cell_id: String,
+        }
+        #[derive(Deserialize)]
+        struct Row {
+            cell: Option<Cell>,
+        }
+        self.api_client
+            .sync_query::<Row>(
+                r#"SELECT notebook_update_cell(notebook_id=notebook_id, cell_id=cell_id,
+                                               type=type, input=input) AS cell
+                   FROM scope()"#,
+                &self.query_options(vec![
+                    ("cell_id".into(), cell_id.into()),
+                    ("type".into(), cell.kind.as_str().into()),
+                    ("input".into(), cell.input.clone()),
+                ]),
+            )
+            .await?
+            .into_iter()
+            .next()
+            .and_then(|r| r.cell)
+            .map(|c| c.cell_id)
+            .ok_or_else(|| APIClientError::VQL("notebook_update_cell() failed".into()))
+    }
+
+    /// Metadata of the notebook's cells, in order
+    async fn cell_metadata(&self) -> Result<Vec<CellMetadata>, APIClientError> {
+        #[derive(Deserialize)]
+        struct Metadata {
+            #[serde(default)]
+            cell_metadata: Option<Vec<CellMetadata>>,
+        }
+        #[derive(Deserialize)]
+        struct Row {
+            notebook: Option<Metadata>,
+        }
+        Ok(self
+            .api_client
+            .sync_query::<Row>(
+                r#"SELECT notebook_get(notebook_id=notebook_id) AS notebook FROM scope()"#,
+                &self.query_options(vec![]),
+            )
+            .await?
+            .into_iter()
+            .next()
+            .and_then(|r| r.notebook)
+            .and_then(|n| n.cell_metadata)
+            .unwrap_or_default())
+    }
+
+    /// IDs of the notebook's cells, in order
+    pub async fn cell_ids(&self) -> Result<Vec<String>, APIClientError> {
+        Ok(self
+            .cell_metadata()
+            .await?
+            .into_iter()
+            .map(|c| c.cell_id)
+            .collect())
+    }
+
+    /// Recalculate all cells of the notebook, keeping their content
+    pub async fn recalculate(&self) -> Result<(), APIClientError> {
+        for cell in self.cell_metadata().await? {
+            log::debug!("Recalculating {} / {}", self.notebook_id, cell.cell_id);
+            let input = cell.input.ok_or_else(|| {
+                APIClientError::VQL(format!(
+                    "notebook_get() did not return the input of cell {}",
+                    cell.cell_id
+                ))
+            })?;
+            let kind = match cell.kind.to_lowercase().as_str() {
+                "vql" => CellType::Vql,
+                _ => CellType::Markdown,
+            };
+            self.update_cell(&cell.cell_id, &NotebookCell { kind, input })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Export the notebook on the server and fetch the result
+    pub async fn export(&self, format: ExportFormat) -> Result<Vec<u8>, APIClientError> {
+        #[derive(Deserialize)]
+        struct Export {
+            #[serde(rename = "Components")]
+            components: Vec<String>,
+        }
+        #[derive(Deserialize)]
+        struct Row {
+            export: Option<Export>,
+        }
+        let export = self
+            .api_client
+            .sync_query::<Row>(
+                r#"SELECT notebook_export(notebook_id=notebook_id, type=format) AS export
+                   FROM scope()"#,
+                &self.query_options(vec![("format".into(), format.as_str().into())]),
+            )
+            .await?
+            .into_iter()
+            .next()
+            .and_then(|r| r.export)
+            .ok_or_else(|| APIClientError::VQL("notebook_export() failed".into()))?;
+        self.api_client.fetch(VfsPath::new(export.components)).await
+    }
+
+    /// List the notebook's attachments
+    pub async fn attachments(&self) -> Result<Vec<FilestoreEntry>, APIClientError> {
+        self.api_client
+            .ls(VfsPath::notebook_attachments(&self.notebook_id))
+            .await
+    }
+
+    /// Upload an attachment, e.g. an image or CSV file, to the notebook.
+    /// Returns the attachment's filestore path.
+    pub async fn upload_attachment<R: AsyncRead + Unpin>(
+        &self,
+        name: &str,
+        reader: &mut R,
+    ) -> Result<VfsPath, APIClientError> {
+        let path = VfsPath::notebook_attachments(&self.notebook_id).join(name);
+        self.api_client.upload(&path, reader).await?;
+        Ok(path)
+    }
+
+    /// Download an attachment of the notebook
+    pub async fn download_attachment(&self, name: &str) -> Result<Vec<u8>, APIClientError> {
+        self.api_client
+            .fetch(VfsPath::notebook_attachments(&self.notebook_id).join(name))
+            .await
+    }
+}
+
+impl ClientFlow<'_> {
+    /// Retrieve the notebook attached to the flow, creating it if
+    /// necessary
+    pub async fn notebook(&self) -> Result<Notebook<'_>, APIClientError> {
+        self.api_client
+            .notebook_get_or_create(
+                &format!("N.{}-{}", self.flow_id, self.client_id),
+                &format!("Flow {} on {}", self.flow_id, self.client_id),
+            )
+            .await
+    }
+}
+
+impl Hunt<'_> {
+    /// Retrieve the notebook attached to the hunt, creating it if
+    /// necessary
+    pub async fn notebook(&self) -> Result<Notebook<'_>, APIClientError> {
+        self.api_client
+            .notebook_get_or_create(
+                &format!("N.{}", self.hunt_id),
+                &format!("Hunt {}", self.hunt_id),
+            )
+            .await
+    }
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{APIClient, APIClientError, QueryOptions};
+
+/// An organization on a multi-tenant server
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Org {
+    pub id: String,
+    pub name: String,
+}
+
+impl APIClient {
+    /// List all organizations
+    pub async fn orgs(&self) -> Result<Vec<Org>, APIClientError> {
+        self.sync_query(r#"SELECT * FROM orgs()"#, &QueryOptions::builder().build())
+            .await
+    }
+
+    /// Create an organization. The caller is added to it as
+    /// administrator.
+    pub async fn create_org(&self, name: &str) -> Result<Org, APIClientError> {
+        #[derive(Deserialize)]
+        struct Row {
+            org: Option<Org>,
+        }
+        self.sync_query::<Row>(
+            r#"SELECT org_create(name=name) AS org FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![("name".into(), name.into())])
+                .build(),
+        )
+        .await?
+        .into_iter()
+        .next()
+        .and_then(|r| r.org)
+        .ok_or_else(|| APIClientError::VQL("org_create() failed".into()))
+    }
+
+    /// Delete an organization and all its data
+    pub async fn delete_org(&self, org_id: &str) -> Result<(), APIClientError> {
+        self.sync_query::<serde_json::Value>(
+            r#"SELECT org_delete(org=org_id, really_do_it=TRUE) FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![("org_id".into(), org_id.into())])
+                .build(),
+        )
+        .await?;
+        Ok(())
+    }
+}
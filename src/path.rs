@@ -0,0 +1,203 @@
+use std::path::{Component, Path, PathBuf};
+
+/// A path in the server's filestore, represented as a list of
+/// components. Components are passed to the server verbatim, so they
+/// may contain characters such as `/`, `\` or `:` that would otherwise
+/// be interpreted as separators or prefixes.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VfsPath {
+    components: Vec<String>,
+}
+
+impl VfsPath {
+    /// Construct a path from its components
+    pub fn new<I, S>(components: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            components: components.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Directory containing download archives created for a flow
+    pub fn downloads(client_id: &str, flow_id: &str) -> Self {
+        Self::new(["downloads", client_id, flow_id])
+    }
+
+    /// Directory containing results and metadata of a flow
+    pub fn collection(client_id: &str, flow_id: &str) -> Self {
+        Self::new(["clients", client_id, "collections", flow_id])
+    }
+
+    /// Directory containing files uploaded by a flow
+    pub fn uploads(client_id: &str, flow_id: &str) -> Self {
+        Self::collection(client_id, flow_id).join("uploads")
+    }
+
+    /// Directory containing hunt download archives
+    pub fn hunt_downloads(hunt_id: &str) -> Self {
+        Self::new(["downloads", "hunts", hunt_id])
+    }
+
+    /// Directory containing the attachments of a notebook. Notebooks
+    /// of flows (`N.F.<flow>-<client>`) and hunts (`N.H.<hunt>`) are
+    /// stored with the flow or hunt, all others under `notebooks`.
+    pub fn notebook_attachments(notebook_id: &str) -> Self {
+        let id = notebook_id.strip_prefix("N.").unwrap_or(notebook_id);
+        let root = if let Some((flow_id, client_id)) =
+            id.split_once('-').filter(|(f, _)| f.starts_with("F."))
+        {
+            Self::collection(client_id, flow_id).join("notebook")
+        } else if id.starts_with("H.") {
+            Self::new(["hunts", id, "notebook"])
+        } else {
+            Self::new(["notebooks"])
+        };
+        root.join(notebook_id).join("attach")
+    }
+
+    /// Server state backup archive with the given file name
+    pub fn backup(name: &str) -> Self {
+        Self::new(["backups", name])
+    }
+
+    /// The server's public directory, served without authentication
+    pub fn public() -> Self {
+        Self::new(["public"])
+    }
+
+    /// Append a single component
+    pub fn join<S: Into<String>>(mut self, component: S) -> Self {
+        self.components.push(component.into());
+        self
+    }
+
+    pub fn components(&self) -> &[String] {
+        &self.components
+    }
+
+    /// Check whether all components are names, i.e. none of them is
+    /// empty, `.` or `..`, so that the path cannot refer to anything
+    /// outside of the directory it is joined to
+    pub fn is_contained(&self) -> bool {
+        self.components
+            .iter()
+            .all(|c| !matches!(c.as_str(), "" | "." | ".."))
+    }
+
+    /// Path as understood by the `fs` accessor. Components containing
+    /// separators or quotes are quoted.
+    pub(crate) fn to_vql_path(&self) -> String {
+        self.components
+            .iter()
+            .map(|c| {
+                if c.contains(['/', '\\', '"']) {
+                    format!("\"{}\"", c.replace('"', "\"\""))
+                } else {
+                    c.clone()
+                }
+            })
+            .fold(String::new(), |acc, c| acc + "/" + &c)
+    }
+}
+
+impl std::fmt::Display for VfsPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.to_vql_path())
+    }
+}
+
+impl From<&VfsPath> for VfsPath {
+    fn from(p: &VfsPath) -> Self {
+        p.clone()
+    }
+}
+
+/// Split a `/`-separated path into components. Empty components,
+/// `.` and `..` are dropped.
+impl From<&str> for VfsPath {
+    fn from(s: &str) -> Self {
+        Self::new(s.split('/').filter(|c| !matches!(*c, "" | "." | "..")))
+    }
+}
+
+impl From<String> for VfsPath {
+    fn from(s: String) -> Self {
+        s.as_str().into()
+    }
+}
+
+/// Convert a local path, using only its normal components. Prefixes,
+/// root directories, `.` and `..` are dropped.
+impl From<&Path> for VfsPath {
+    fn from(p: &Path) -> Self {
+        Self::new(p.components().filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy().to_string()),
+            _ => None,
+        }))
+    }
+}
+
+impl From<&PathBuf> for VfsPath {
+    fn from(p: &PathBuf) -> Self {
+        p.as_path().into()
+    }
+}
+
+impl From<PathBuf> for VfsPath {
+    fn from(p: PathBuf) -> Self {
+        p.as_path().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vql_path() {
+        assert_eq!(
+            VfsPath::new(["downloads", "C.1"]).to_vql_path(),
+            "/downloads/C.1"
+        );
+        assert_eq!(
+            VfsPath::new(["a", "b/c", r"d\e", r#"say "hi""#]).to_vql_path(),
+            r#"/a/"b/c"/"d\e"/"say ""hi""""#
+        );
+        assert_eq!(VfsPath::default().to_vql_path(), "");
+    }
+
+    #[test]
+    fn from_str_drops_relative_components() {
+        let path = VfsPath::from("/a/./b/../c//");
+        assert_eq!(path.components(), ["a", "b", "c"]);
+        assert!(path.is_contained());
+        assert!(!VfsPath::new(["a", ".."]).is_contained());
+    }
+
+    #[test]
+    fn notebook_attachments() {
+        assert_eq!(
+            VfsPath::notebook_attachments("N.123").components(),
+            ["notebooks", "N.123", "attach"]
+        );
+        assert_eq!(
+            VfsPath::notebook_attachments("N.F.ABC-C.123").components(),
+            [
+                "clients",
+                "C.123",
+                "collections",
+                "F.ABC",
+                "notebook",
+                "N.F.ABC-C.123",
+                "attach"
+            ]
+        );
+        assert_eq!(
+            VfsPath::notebook_attachments("N.H.ABC").components(),
+            ["hunts", "H.ABC", "notebook", "N.H.ABC", "attach"]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use futures::stream::StreamExt;
+use tokio::sync::Notify;
+use tokio::time::{sleep, Duration};
+
+use crate::APIClient;
+
+/// How code that waits for a condition on the server, such as a flow
+/// finishing, spaces out its queries
+pub trait PollStrategy: Send + Sync {
+    /// Wait before polling again. `attempt` is the number of polls
+    /// made so far that have not found the condition to be met.
+    fn wait(&self, attempt: u32) -> BoxFuture<'_, ()>;
+}
+
+/// Poll at a fixed interval
+#[derive(Clone, Copy, Debug)]
+pub struct FixedInterval(pub Duration);
+
+impl Default for FixedInterval {
+    fn default() -> Self {
+        Self(Duration::from_millis(100))
+    }
+}
+
+impl PollStrategy for FixedInterval {
+    fn wait(&self, _attempt: u32) -> BoxFuture<'_, ()> {
+        Box::pin(sleep(self.0))
+    }
+}
+
+/// Poll with exponentially increasing delays, each randomly shortened
+/// by up to half so that many waiters don't poll in lockstep
+#[derive(Clone, Copy, Debug)]
+pub struct ExponentialBackoff {
+    /// Delay after the first poll
+    pub initial: Duration,
+    /// Upper bound for the delay
+    pub max: Duration,
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(10),
+        }
+    }
+}
+
+impl PollStrategy for ExponentialBackoff {
+    fn wait(&self, attempt: u32) -> BoxFuture<'_, ()> {
+        let delay = self
+            .initial
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max);
+        Box::pin(sleep(delay.mul_f64(0.5 + random() / 2.0)))
+    }
+}
+
+/// Poll whenever the server reports that a flow has completed, as
+/// seen on the `System.Flow.Completion` event queue, and at least
+/// every `fallback` in case an event is missed.
+///
+/// Events are watched by a background task that runs until the
+/// strategy is dropped, so this must be created within a tokio
+/// runtime.
+pub struct EventDriven {
+    notify: Arc<Notify>,
+    fallback: Duration,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl EventDriven {
+    pub fn new(api_client: &APIClient, fallback: Duration) -> Self {
+        let notify = Arc::new(Notify::new());
+        let api_client = api_client.clone();
+        let completions = notify.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match api_client
+                    .watch_events::<serde_json::Value>("System.Flow.Completion", None)
+                    .await
+                {
+                    Ok(events) => {
+                        let mut events = std::pin::pin!(events);
+                        while let Some(Ok(_)) = events.next().await {
+                            completions.notify_waiters();
+                        }
+                    }
+                    Err(e) => log::warn!("watching flow completions failed: {e}"),
+                }
+                sleep(fallback).await;
+            }
+        });
+        Self {
+            notify,
+            fallback,
+            task,
+        }
+    }
+}
+
+impl Drop for EventDriven {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl PollStrategy for EventDriven {
+    fn wait(&self, _attempt: u32) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            tokio::select! {
+                _ = self.notify.notified() => {}
+                _ = sleep(self.fallback) => {}
+            }
+        })
+    }
+}
+
+/// Pseudo-random number in `[0, 1)`, good enough for jitter
+fn random() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
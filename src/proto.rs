@@ -1 +1,4 @@
+//! Message types and gRPC client generated from the server's API
+//! definition, for requests not covered by the high-level API
+
 include!(concat!(env!("OUT_DIR"), "/proto.rs"));
@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{APIClient, APIClientError, QueryOptions};
+
+/// Outstanding flows of a single client
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ClientBacklog {
+    pub client_id: String,
+    /// Flows that have been scheduled but not yet started by the client
+    pub pending: u64,
+    /// Flows the client is currently working on
+    pub in_progress: u64,
+}
+
+#[derive(Deserialize)]
+struct RunningFlow {
+    client_id: String,
+    #[serde(default)]
+    active_time: u64,
+}
+
+/// Aggregate running flows by client, most pending flows first
+fn backlog(flows: Vec<RunningFlow>) -> Vec<ClientBacklog> {
+    let mut by_client = BTreeMap::<String, ClientBacklog>::new();
+    for flow in flows {
+        let entry = by_client
+            .entry(flow.client_id.clone())
+            .or_insert_with(|| ClientBacklog {
+                client_id: flow.client_id,
+                ..ClientBacklog::default()
+            });
+        match flow.active_time {
+            0 => entry.pending += 1,
+            _ => entry.in_progress += 1,
+        }
+    }
+    let mut result: Vec<_> = by_client.into_values().collect();
+    result.sort_by_key(|b| std::cmp::Reverse((b.pending, b.in_progress)));
+    result
+}
+
+impl APIClient {
+    /// Outstanding flows of a single client
+    pub async fn client_backlog(&self, client_id: &str) -> Result<ClientBacklog, APIClientError> {
+        let flows = self
+            .sync_query::<RunningFlow>(
+                r#"SELECT client_id, active_time FROM flows(client_id=client_id)
+                   WHERE state = "RUNNING""#,
+                &QueryOptions::builder()
+                    .env(vec![("client_id".into(), client_id.into())])
+                    .build(),
+            )
+            .await?;
+        Ok(backlog(flows).pop().unwrap_or_else(|| ClientBacklog {
+            client_id: client_id.to_string(),
+            ..ClientBacklog::default()
+        }))
+    }
+
+    /// Outstanding flows of all clients that have any, most pending
+    /// flows first. This inspects the flows of every client and can
+    /// be slow on large deployments.
+    pub async fn flow_backlog(&self) -> Result<Vec<ClientBacklog>, APIClientError> {
+        let flows = self
+            .sync_query::<RunningFlow>(
+                r#"SELECT * FROM foreach(
+                     row={ SELECT client_id FROM clients() },
+                     query={ SELECT client_id, active_time FROM flows(client_id=client_id)
+                             WHERE state = "RUNNING" },
+                     workers=10)"#,
+                &QueryOptions::builder().build(),
+            )
+            .await?;
+        Ok(backlog(flows))
+    }
+}
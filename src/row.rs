@@ -0,0 +1,171 @@
+//! Helpers for deserializing result rows, as used by
+//! [`ArtifactRow`](crate::ArtifactRow). They can also be used directly
+//! with serde's `deserialize_with` attribute.
+
+use base64::prelude::*;
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::{Serialize, Serializer};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+#[doc(hidden)]
+pub use serde;
+
+/// Treat explicit `null` values like missing fields
+pub fn null_as_default<'de, D, T>(d: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(d)?.unwrap_or_default())
+}
+
+/// Parse a timestamp in any of the encodings accepted by
+/// [`VrTimestamp`]
+pub fn timestamp<'de, D>(d: D) -> Result<time::OffsetDateTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(VrTimestamp::deserialize(d)?.0)
+}
+
+/// Decode a base64-encoded string
+pub fn base64<'de, D>(d: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    BASE64_STANDARD
+        .decode(String::deserialize(d)?)
+        .map_err(D::Error::custom)
+}
+
+/// Point in time as found in query results.
+///
+/// Velociraptor encodes timestamps in several ways. When deserialized,
+/// this type accepts integers counting seconds, milliseconds,
+/// microseconds, or nanoseconds since the epoch (the unit is guessed
+/// from the magnitude), fractional seconds since the epoch, and
+/// RFC3339 strings. Empty strings, zero, and `null` denote the epoch
+/// itself. It is serialized as an RFC3339 string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VrTimestamp(pub OffsetDateTime);
+
+impl VrTimestamp {
+    /// Check whether the timestamp is unset, i.e. the epoch
+    pub fn is_unset(&self) -> bool {
+        self.0 == OffsetDateTime::UNIX_EPOCH
+    }
+
+    fn from_int(n: i64) -> Option<Self> {
+        let nanos = match n.unsigned_abs() {
+            0..=99_999_999_999 => n as i128 * 1_000_000_000,
+            100_000_000_000..=99_999_999_999_999 => n as i128 * 1_000_000,
+            100_000_000_000_000..=99_999_999_999_999_999 => n as i128 * 1_000,
+            _ => n as i128,
+        };
+        OffsetDateTime::from_unix_timestamp_nanos(nanos)
+            .ok()
+            .map(Self)
+    }
+}
+
+impl Default for VrTimestamp {
+    fn default() -> Self {
+        Self(OffsetDateTime::UNIX_EPOCH)
+    }
+}
+
+impl From<OffsetDateTime> for VrTimestamp {
+    fn from(t: OffsetDateTime) -> Self {
+        Self(t)
+    }
+}
+
+impl From<VrTimestamp> for OffsetDateTime {
+    fn from(t: VrTimestamp) -> Self {
+        t.0
+    }
+}
+
+impl std::fmt::Display for VrTimestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.0.format(&Rfc3339) {
+            Ok(s) => f.write_str(&s),
+            Err(_) => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl Serialize for VrTimestamp {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for VrTimestamp {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Int(i64),
+            Float(f64),
+            Text(String),
+            Null(()),
+        }
+        let invalid = || D::Error::custom("timestamp out of range");
+        match Raw::deserialize(d)? {
+            Raw::Int(n) => Self::from_int(n).ok_or_else(invalid),
+            Raw::Float(s) => OffsetDateTime::from_unix_timestamp_nanos((s * 1e9) as i128)
+                .map(Self)
+                .map_err(|_| invalid()),
+            Raw::Text(s) if s.is_empty() => Ok(Self::default()),
+            Raw::Null(()) => Ok(Self::default()),
+            Raw::Text(s) => match s.parse::<i64>() {
+                Ok(n) => Self::from_int(n).ok_or_else(invalid),
+                Err(_) => OffsetDateTime::parse(&s, &Rfc3339)
+                    .map(Self)
+                    .map_err(D::Error::custom),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> OffsetDateTime {
+        serde_json::from_str::<VrTimestamp>(json).unwrap().0
+    }
+
+    #[test]
+    fn timestamp_encodings() {
+        let t = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        assert_eq!(parse("1700000000"), t);
+        assert_eq!(parse("1700000000000"), t);
+        assert_eq!(parse("1700000000000000"), t);
+        assert_eq!(parse("1700000000000000000"), t);
+        assert_eq!(parse("1700000000.0"), t);
+        assert_eq!(parse(r#""1700000000""#), t);
+        assert_eq!(parse(r#""2023-11-14T22:13:20Z""#), t);
+        assert_eq!(parse(r#""2023-11-14T23:13:20+01:00""#), t);
+    }
+
+    #[test]
+    fn unset_timestamps() {
+        for json in ["0", r#""""#, "null"] {
+            assert!(serde_json::from_str::<VrTimestamp>(json)
+                .unwrap()
+                .is_unset());
+        }
+        assert!(serde_json::from_str::<VrTimestamp>(r#""yesterday""#).is_err());
+    }
+
+    #[test]
+    fn timestamp_round_trip() {
+        let t = VrTimestamp(OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap());
+        let json = serde_json::to_string(&t).unwrap();
+        assert_eq!(json, r#""2023-11-14T22:13:20Z""#);
+        assert_eq!(serde_json::from_str::<VrTimestamp>(&json).unwrap(), t);
+    }
+}
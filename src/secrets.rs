@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::row::null_as_default;
+use crate::{APIClient, APIClientError, QueryOptions};
+
+/// A secret stored on the server. Secret values can only be used by
+/// artifacts and are never returned by the server.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Secret {
+    /// Type of the secret, e.g. `AWS S3 Creds` or `SSH PrivateKey`
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub name: String,
+    /// Users allowed to use the secret
+    #[serde(deserialize_with = "null_as_default")]
+    pub users: Vec<String>,
+}
+
+impl APIClient {
+    /// List secrets accessible to the caller
+    pub async fn secrets(&self) -> Result<Vec<Secret>, APIClientError> {
+        self.sync_query(
+            r#"SELECT * FROM secrets()"#,
+            &QueryOptions::builder().build(),
+        )
+        .await
+    }
+
+    /// Store a secret of the given type, usable by `users`. The fields
+    /// required in `values` depend on the secret type.
+    pub async fn add_secret(
+        &self,
+        kind: &str,
+        name: &str,
+        values: &HashMap<String, String>,
+        users: &[&str],
+    ) -> Result<(), APIClientError> {
+        self.sync_query::<serde_json::Value>(
+            r#"SELECT secret_add(type=type, name=name, secret=parse_json(data=values),
+                                 users=parse_json_array(data=users)) FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![
+                    ("type".into(), kind.into()),
+                    ("name".into(), name.into()),
+                    ("values".into(), serde_json::to_string(values).unwrap()),
+                    ("users".into(), serde_json::to_string(users).unwrap()),
+                ])
+                .build(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Grant or revoke users' access to a secret
+    pub async fn share_secret(
+        &self,
+        kind: &str,
+        name: &str,
+        add_users: &[&str],
+        remove_users: &[&str],
+    ) -> Result<(), APIClientError> {
+        self.sync_query::<serde_json::Value>(
+            r#"SELECT secret_modify(type=type, name=name,
+                                    add_users=parse_json_array(data=add_users),
+                                    remove_users=parse_json_array(data=remove_users))
+               FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![
+                    ("type".into(), kind.into()),
+                    ("name".into(), name.into()),
+                    (
+                        "add_users".into(),
+                        serde_json::to_string(add_users).unwrap(),
+                    ),
+                    (
+                        "remove_users".into(),
+                        serde_json::to_string(remove_users).unwrap(),
+                    ),
+                ])
+                .build(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Delete a secret
+    pub async fn delete_secret(&self, kind: &str, name: &str) -> Result<(), APIClientError> {
+        self.sync_query::<serde_json::Value>(
+            r#"SELECT secret_modify(type=type, name=name, delete=TRUE) FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![
+                    ("type".into(), kind.into()),
+                    ("name".into(), name.into()),
+                ])
+                .build(),
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+use futures::stream::{Stream, TryStreamExt};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{APIClient, APIClientError, QueryOptions, VrTimestamp};
+
+/// Server component whose log is to be read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServerComponent {
+    /// Messages not attributed to any specific component
+    General,
+    Frontend,
+    Api,
+    Gui,
+}
+
+impl ServerComponent {
+    /// Log file name prefix used by the server for the component
+    fn log_prefix(&self) -> &'static str {
+        match self {
+            Self::General => "Velociraptor",
+            Self::Frontend => "VelociraptorFrontend",
+            Self::Api => "VelociraptorAPI",
+            Self::Gui => "VelociraptorGUI",
+        }
+    }
+}
+
+impl std::str::FromStr for ServerComponent {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "general" => Ok(Self::General),
+            "frontend" => Ok(Self::Frontend),
+            "api" => Ok(Self::Api),
+            "gui" => Ok(Self::Gui),
+            _ => Err(format!("unknown server component: {s}")),
+        }
+    }
+}
+
+/// Server log level
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Error => "error",
+        }
+    }
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "debug" => Ok(Self::Debug),
+            "info" => Ok(Self::Info),
+            "warn" | "warning" => Ok(Self::Warn),
+            "error" => Ok(Self::Error),
+            _ => Err(format!("unknown log level: {s}")),
+        }
+    }
+}
+
+/// A single line from a server log file
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ServerLogLine {
+    /// Name of the log file the line was read from
+    #[serde(rename = "File")]
+    pub file: String,
+    #[serde(rename = "Line")]
+    pub line: String,
+}
+
+/// Version and build information of the server
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServerInfo {
+    pub name: String,
+    pub version: String,
+    pub commit: String,
+    pub build_time: String,
+    pub compiler: String,
+}
+
+impl ServerInfo {
+    /// Numeric components of the version, e.g. `[0, 7, 1]` for
+    /// `0.7.1-rc1`, for comparisons such as `>= [0, 7]`
+    pub fn version_tuple(&self) -> Vec<u32> {
+        self.version
+            .split(['.', '-'])
+            .map_while(|c| c.parse().ok())
+            .collect()
+    }
+}
+
+/// Event artifact recording server health metrics
+const HEALTH_ARTIFACT: &str = "Server.Monitor.Health/Prometheus";
+
+/// A sample of server health metrics, as recorded every few seconds
+/// by the `Server.Monitor.Health` event artifact
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServerHealth {
+    /// Time of the sample
+    #[serde(rename = "_ts")]
+    pub timestamp: VrTimestamp,
+    #[serde(rename = "CPUPercent")]
+    pub cpu_percent: f64,
+    /// Resident memory of the frontend, in bytes
+    #[serde(rename = "MemoryUse")]
+    pub memory_use: u64,
+    /// Number of clients currently connected
+    #[serde(rename = "client_comms_current_connections")]
+    pub connected_clients: u64,
+    #[serde(rename = "TotalFrontends")]
+    pub total_frontends: u64,
+    /// All other metrics of the sample, e.g. query and flow rates
+    #[serde(flatten)]
+    pub metrics: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Replacement for redacted configuration values
+pub(crate) const REDACTED: &str = "<redacted>";
+
+/// Whether a configuration key names a secret value
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+    [
+        "private_key",
+        "password",
+        "secret",
+        "token",
+        "nonce",
+        "api_key",
+    ]
+    .iter()
+    .any(|s| key.contains(s))
+}
+
+/// Recursively replace secret values in a configuration tree
+pub(crate) fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_secret_key(key) && !value.is_null() {
+                    *value = REDACTED.into();
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+impl APIClient {
+    /// The server's effective configuration. Private keys, passwords,
+    /// and similar secrets are replaced by `<redacted>` before the
+    /// configuration is returned.
+    pub async fn server_config(&self) -> Result<serde_json::Value, APIClientError> {
+        #[derive(Deserialize)]
+        struct Row {
+            config: Option<serde_json::Value>,
+        }
+        let mut config = self
+            .sync_query::<Row>(
+                r#"SELECT server_config AS config FROM scope()"#,
+                &QueryOptions::builder().build(),
+            )
+            .await?
+            .into_iter()
+            .next()
+            .and_then(|r| r.config)
+            .ok_or_else(|| APIClientError::VQL("server_config not available".into()))?;
+        redact(&mut config);
+        Ok(config)
+    }
+
+    /// Most recent server health sample, if one was recorded in the
+    /// last minute. This requires `Server.Monitor.Health` to be enabled
+    /// in server monitoring, which it is by default.
+    pub async fn server_health(&self) -> Result<Option<ServerHealth>, APIClientError> {
+        let end = time::OffsetDateTime::now_utc();
+        let rows = self.events_between::<ServerHealth>(
+            HEALTH_ARTIFACT,
+            end - time::Duration::minutes(1),
+            end,
+        );
+        rows.try_fold(None, |_, row| async move { Ok(Some(row)) })
+            .await
+    }
+
+    /// Version and build information of the connected server
+    pub async fn server_info(&self) -> Result<ServerInfo, APIClientError> {
+        #[derive(Deserialize)]
+        struct Row {
+            version: Option<ServerInfo>,
+        }
+        self.sync_query::<Row>(
+            r#"SELECT server_config.version AS version FROM scope()"#,
+            &QueryOptions::builder().build(),
+        )
+        .await?
+        .into_iter()
+        .next()
+        .and_then(|r| r.version)
+        .ok_or_else(|| APIClientError::VQL("server_config.version not available".into()))
+    }
+
+    fn server_log_options(component: Option<ServerComponent>, level: LogLevel) -> QueryOptions {
+        let pattern = match component {
+            Some(c) => format!("{}_{}.log", c.log_prefix(), level.as_str()),
+            None => format!("Velociraptor*_{}.log", level.as_str()),
+        };
+        QueryOptions::builder()
+            .env(vec![("pattern".into(), pattern)])
+            .build()
+    }
+
+    /// Read server log files of the given level, for one or all
+    /// components. This requires the server to log to files
+    /// (`Logging.output_directory`).
+    pub async fn server_logs(
+        &self,
+        component: Option<ServerComponent>,
+        level: LogLevel,
+    ) -> Result<Vec<ServerLogLine>, APIClientError> {
+        self.sync_query(
+            r#"SELECT * FROM foreach(
+                 row={ SELECT OSPath FROM glob(
+                         globs=pattern, root=server_config.Logging.output_directory) },
+                 query={ SELECT OSPath.Basename AS File, Line
+                         FROM parse_lines(filename=OSPath) })"#,
+            &Self::server_log_options(component, level),
+        )
+        .await
+    }
+
+    /// Follow server log files of the given level, for one or all
+    /// components, returning lines as they are written
+    pub async fn tail_server_logs(
+        &self,
+        component: Option<ServerComponent>,
+        level: LogLevel,
+    ) -> Result<impl Stream<Item = Result<ServerLogLine, APIClientError>>, APIClientError> {
+        self.query_stream(
+            r#"SELECT * FROM foreach(
+                 row={ SELECT OSPath FROM glob(
+                         globs=pattern, root=server_config.Logging.output_directory) },
+                 query={ SELECT OSPath.Basename AS File, Line
+                         FROM watch_syslog(filename=OSPath) },
+                 workers=10)"#,
+            &Self::server_log_options(component, level),
+        )
+        .await
+    }
+}
@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+
+use crate::row::null_as_default;
+use crate::{APIClient, APIClientError, Artifact, QueryOptions};
+
+/// Server artifact used to issue API client certificates. It is not
+/// part of the standard artifact set; it wraps `velociraptor config
+/// api_client` and returns the resulting configuration in a `Config`
+/// column. Its definition is [`API_CLIENT_ARTIFACT_DEFINITION`].
+pub const API_CLIENT_ARTIFACT: &str = "Server.Utils.CreateApiClient";
+
+/// Definition of [`API_CLIENT_ARTIFACT`], installed by
+/// [`APIClient::install_api_client_artifact`]
+pub const API_CLIENT_ARTIFACT_DEFINITION: &str =
+    include_str!("../artifacts/Server.Utils.CreateApiClient.yaml");
+
+/// An organization a user is a member of
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OrgMembership {
+    pub id: String,
+    pub name: String,
+}
+
+/// A user account on the server
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct User {
+    pub name: String,
+    pub email: String,
+    pub locked: bool,
+    #[serde(deserialize_with = "null_as_default")]
+    pub orgs: Vec<OrgMembership>,
+}
+
+/// Roles and permissions granted to a user in an organization
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UserPermissions {
+    #[serde(deserialize_with = "null_as_default")]
+    pub roles: Vec<String>,
+    /// Permissions granted directly or through roles, e.g.
+    /// `COLLECT_CLIENT`
+    #[serde(deserialize_with = "null_as_default")]
+    pub permissions: Vec<String>,
+}
+
+impl UserPermissions {
+    /// Check whether a permission such as `COLLECT_CLIENT` is granted
+    pub fn has(&self, permission: &str) -> bool {
+        self.permissions
+            .iter()
+            .any(|p| p.eq_ignore_ascii_case(permission))
+    }
+}
+
+/// Identity of the API client as seen by the server
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct WhoAmI {
+    pub name: String,
+    pub orgs: Vec<OrgMembership>,
+    /// Effective permissions in the client's organization
+    pub permissions: UserPermissions,
+}
+
+fn json_list(items: &[&str]) -> String {
+    serde_json::to_string(items).unwrap()
+}
+
+impl APIClient {
+    /// List users of all organizations
+    pub async fn users(&self) -> Result<Vec<User>, APIClientError> {
+        self.sync_query(
+            r#"SELECT * FROM users(all_orgs=TRUE)"#,
+            &QueryOptions::builder().build(),
+        )
+        .await
+    }
+
+    /// Create a user with the given roles in the given organizations.
+    /// If `orgs` is empty, the user is created in the client's
+    /// organization. An empty `password` creates a user that can only
+    /// log in through SSO.
+    pub async fn create_user(
+        &self,
+        name: &str,
+        password: &str,
+        roles: &[&str],
+        orgs: &[&str],
+    ) -> Result<(), APIClientError> {
+        self.sync_query::<serde_json::Value>(
+            r#"SELECT user_create(user=name, password=password,
+                                  roles=parse_json_array(data=roles),
+                                  orgs=parse_json_array(data=orgs)) FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![
+                    ("name".into(), name.into()),
+                    ("password".into(), password.into()),
+                    ("roles".into(), json_list(roles)),
+                    ("orgs".into(), json_list(orgs)),
+                ])
+                .build(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Set a user's password. This only applies to servers using
+    /// basic authentication.
+    pub async fn set_password(&self, name: &str, password: &str) -> Result<(), APIClientError> {
+        self.sync_query::<serde_json::Value>(
+            r#"SELECT passwd(username=name, password=password) FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![
+                    ("name".into(), name.into()),
+                    ("password".into(), password.into()),
+                ])
+                .build(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Add an existing user to organizations, with the given roles
+    pub async fn add_user_to_orgs(
+        &self,
+        name: &str,
+        roles: &[&str],
+        orgs: &[&str],
+    ) -> Result<(), APIClientError> {
+        self.create_user(name, "", roles, orgs).await
+    }
+
+    /// Replace a user's roles and additional permissions in the
+    /// client's organization. Permissions are given by name, e.g.
+    /// `COLLECT_CLIENT`.
+    pub async fn grant(
+        &self,
+        user: &str,
+        roles: &[&str],
+        permissions: &[&str],
+    ) -> Result<(), APIClientError> {
+        let policy = permissions
+            .iter()
+            .map(|p| (p.to_lowercase(), serde_json::Value::Bool(true)))
+            .collect::<serde_json::Map<_, _>>();
+        self.sync_query::<serde_json::Value>(
+            r#"SELECT user_grant(user=name, roles=parse_json_array(data=roles),
+                                 policy=parse_json(data=policy)) FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![
+                    ("name".into(), user.into()),
+                    ("roles".into(), json_list(roles)),
+                    (
+                        "policy".into(),
+                        serde_json::Value::Object(policy).to_string(),
+                    ),
+                ])
+                .build(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Roles and effective permissions of a user in the client's
+    /// organization
+    pub async fn user_permissions(&self, user: &str) -> Result<UserPermissions, APIClientError> {
+        #[derive(Deserialize)]
+        struct Row {
+            user: Option<UserPermissions>,
+        }
+        self.sync_query::<Row>(
+            r#"SELECT user(name=name) AS user FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![("name".into(), user.into())])
+                .build(),
+        )
+        .await?
+        .into_iter()
+        .next()
+        .and_then(|r| r.user)
+        .ok_or_else(|| APIClientError::VQL("user() failed".into()))
+    }
+
+    /// Identity, organization memberships, and effective permissions
+    /// of the API client itself. Applications can use this to disable
+    /// features the client lacks permissions for.
+    pub async fn whoami(&self) -> Result<WhoAmI, APIClientError> {
+        #[derive(Deserialize)]
+        struct Details {
+            #[serde(default, deserialize_with = "null_as_default")]
+            orgs: Vec<OrgMembership>,
+            #[serde(flatten)]
+            permissions: UserPermissions,
+        }
+        #[derive(Deserialize)]
+        struct Row {
+            name: String,
+            user: Option<Details>,
+        }
+        let row = self
+            .sync_query::<Row>(
+                r#"SELECT whoami() AS name, user(name=whoami()) AS user FROM scope()"#,
+                &QueryOptions::builder().build(),
+            )
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| APIClientError::VQL("whoami() failed".into()))?;
+        let details = row
+            .user
+            .ok_or_else(|| APIClientError::VQL("user() failed".into()))?;
+        Ok(WhoAmI {
+            name: row.name,
+            orgs: details.orgs,
+            permissions: details.permissions,
+        })
+    }
+
+    /// Remove a user from the client's organization
+    pub async fn delete_user(&self, name: &str) -> Result<(), APIClientError> {
+        self.sync_query::<serde_json::Value>(
+            r#"SELECT user_delete(user=name, really_do_it=TRUE) FROM scope()"#,
+            &QueryOptions::builder()
+                .env(vec![("name".into(), name.into())])
+                .build(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Add [`API_CLIENT_ARTIFACT`] to the server, which is required by
+    /// [`create_api_client`](Self::create_api_client). The artifact
+    /// expects the server configuration at
+    /// `/etc/velociraptor/server.config.yaml` unless its `ConfigPath`
+    /// parameter default is changed.
+    pub async fn install_api_client_artifact(&self) -> Result<Artifact, APIClientError> {
+        self.set_artifact(API_CLIENT_ARTIFACT_DEFINITION).await
+    }
+
+    /// Issue a certificate for a new API client principal with the
+    /// given roles, using [`API_CLIENT_ARTIFACT`]. Returns the client
+    /// configuration as YAML, as understood by
+    /// [`APIClientConfig::from_yaml_file`](crate::APIClientConfig::from_yaml_file).
+    ///
+    /// Fails with [`APIClientError::UnknownArtifact`] if the artifact has
+    /// not been installed with
+    /// [`install_api_client_artifact`](Self::install_api_client_artifact).
+    pub async fn create_api_client(
+        &self,
+        name: &str,
+        roles: &[&str],
+    ) -> Result<String, APIClientError> {
+        #[derive(Deserialize)]
+        struct Row {
+            #[serde(rename = "Config")]
+            config: String,
+        }
+        self.artifact(API_CLIENT_ARTIFACT).await?;
+        self.sync_query::<Row>(
+            &format!(
+                r#"SELECT Config FROM Artifact.{API_CLIENT_ARTIFACT}(Name=name, Roles=roles)"#
+            ),
+            &QueryOptions::builder()
+                .env(vec![
+                    ("name".into(), name.into()),
+                    ("roles".into(), roles.join(",")),
+                ])
+                .build(),
+        )
+        .await?
+        .into_iter()
+        .next()
+        .map(|r| r.config)
+        .ok_or_else(|| APIClientError::VQL(format!("{API_CLIENT_ARTIFACT} returned no config")))
+    }
+
+    /// Revoke an API client's access everywhere by removing its
+    /// principal from every organization it is a member of, not only
+    /// from the client's organization. To revoke access to a single
+    /// organization, use [`delete_user`](Self::delete_user) on a client
+    /// scoped to it with [`with_org`](Self::with_org). The certificate
+    /// itself stays valid until it expires, but no longer grants any
+    /// permissions.
+    pub async fn revoke_api_client(&self, name: &str) -> Result<(), APIClientError> {
+        let user = self
+            .users()
+            .await?
+            .into_iter()
+            .find(|u| u.name == name)
+            .ok_or_else(|| APIClientError::VQL(format!("unknown API client: {name}")))?;
+        for org in &user.orgs {
+            self.with_org(&org.id).delete_user(name).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+
+use tokio::io::AsyncWrite;
+
+use crate::{APIClientError, Client, VfsPath, VrTimestamp};
+
+/// A directory entry as listed by `System.VFS.ListDirectory`
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VfsEntry {
+    #[serde(rename = "Name")]
+    pub name: String,
+    /// Full path on the client
+    #[serde(rename = "OSPath")]
+    pub path: String,
+    #[serde(rename = "Size")]
+    pub size: u64,
+    /// Mode string, e.g. `drwxr-xr-x`
+    #[serde(rename = "Mode")]
+    pub mode: String,
+    /// Modification time
+    pub mtime: VrTimestamp,
+}
+
+impl VfsEntry {
+    pub fn is_dir(&self) -> bool {
+        self.mode.starts_with('d')
+    }
+}
+
+impl Client<'_> {
+    /// Refresh the server's view of the client's virtual file system
+    /// below `path` by collecting `System.VFS.ListDirectory`, and wait
+    /// for the collection to finish
+    pub async fn vfs_refresh(&self, path: &str, depth: u32) -> Result<(), APIClientError> {
+        self.vfs_list_directory(path, depth).await.map(|_| ())
+    }
+
+    /// List the directory `path` on the client. This refreshes the
+    /// server's view of the directory as a side effect.
+    pub async fn vfs_ls(&self, path: &str) -> Result<Vec<VfsEntry>, APIClientError> {
+        self.vfs_list_directory(path, 0).await
+    }
+
+    async fn vfs_list_directory(
+        &self,
+        path: &str,
+        depth: u32,
+    ) -> Result<Vec<VfsEntry>, APIClientError> {
+        let flow = self
+            .collect(
+                "System.VFS.ListDirectory",
+                &[
+                    ("Path".to_string(), path.to_string()),
+                    ("Depth".to_string(), depth.to_string()),
+                ],
+            )
+            .await?;
+        log::debug!("VFS refresh flow for {self}: {flow}");
+        flow.wait().await?;
+        flow.results().await
+    }
+
+    /// Retrieve the file `path` from the client by collecting
+    /// `System.VFS.DownloadFile` and write its contents to `writer`.
+    /// Returns the number of bytes written.
+    pub async fn vfs_download<W>(&self, path: &str, writer: &mut W) -> Result<u64, APIClientError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let flow = self
+            .collect(
+                "System.VFS.DownloadFile",
+                &[("Path".to_string(), path.to_string())],
+            )
+            .await?;
+        log::debug!("VFS download flow for {self}: {flow}");
+        flow.wait().await?;
+        let uploads = VfsPath::uploads(&self.client_id, &flow.flow_id);
+        let upload = self
+            .api_client
+            .glob(&format!("{uploads}/**"))
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| APIClientError::FlowFailed(format!("{flow}: no file uploaded")))?;
+        self.api_client.fetch_to(&upload, writer).await
+    }
+}
@@ -0,0 +1,147 @@
+use serde::de::DeserializeOwned;
+
+use crate::{APIClientError, ClientFlow, FlowLogEntry};
+
+/// Progress of a flow, as far as a [`FlowWaiter`] has observed it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Phase {
+    Running,
+    Finished,
+    Failed,
+}
+
+/// Resumable waiting for a flow's completion, results, and log.
+///
+/// The futures returned by the methods of this type are
+/// cancellation-safe: if one of them is dropped before it completes,
+/// e.g. because another branch of `tokio::select!` was taken, no
+/// progress is lost, and calling the method again resumes waiting
+/// from the state observed so far.
+pub struct FlowWaiter<'a> {
+    flow: &'a ClientFlow<'a>,
+    phase: Phase,
+    /// Number of unsuccessful polls in the current phase
+    attempt: u32,
+}
+
+impl<'a> ClientFlow<'a> {
+    /// Create a waiter for the flow
+    pub fn waiter(&'a self) -> FlowWaiter<'a> {
+        FlowWaiter {
+            flow: self,
+            phase: Phase::Running,
+            attempt: 0,
+        }
+    }
+}
+
+impl FlowWaiter<'_> {
+    /// Wait before polling again, as determined by the client's
+    /// [`PollStrategy`](crate::PollStrategy)
+    async fn pause(&mut self) {
+        self.flow.api_client.poll.wait(self.attempt).await;
+        self.attempt += 1;
+    }
+
+    fn enter(&mut self, phase: Phase) {
+        self.phase = phase;
+        self.attempt = 0;
+    }
+
+    /// Check whether the flow has been observed to be finished
+    pub fn is_finished(&self) -> bool {
+        self.phase != Phase::Running
+    }
+
+    /// Wait for the flow to finish
+    pub async fn finished(&mut self) -> Result<(), APIClientError> {
+        while self.phase == Phase::Running {
+            match self.flow.state().await?.as_str() {
+                "FINISHED" => self.enter(Phase::Finished),
+                "ERROR" => self.enter(Phase::Failed),
+                _ => self.pause().await,
+            }
+        }
+        match self.phase {
+            Phase::Failed => Err(APIClientError::FlowFailed(self.flow.flow_id.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Wait for the flow to stop running, then for its results to
+    /// become available, and return them. If the flow finished without
+    /// collecting any rows, an empty list is returned. If it failed
+    /// without producing results, [`APIClientError::FlowFailed`] is
+    /// returned.
+    pub async fn results<T: DeserializeOwned>(&mut self) -> Result<Vec<T>, APIClientError> {
+        self.results_of(None).await
+    }
+
+    /// Like `results`, but only those of `artifact`, see
+    /// [`ClientFlow::artifact_results`]
+    pub async fn artifact_results<T: DeserializeOwned>(
+        &mut self,
+        artifact: &str,
+    ) -> Result<Vec<T>, APIClientError> {
+        self.results_of(Some(artifact)).await
+    }
+
+    async fn results_of<T: DeserializeOwned>(
+        &mut self,
+        artifact: Option<&str>,
+    ) -> Result<Vec<T>, APIClientError> {
+        while self.phase == Phase::Running {
+            match self.flow.state().await?.as_str() {
+                "RUNNING" => self.pause().await,
+                "ERROR" => self.enter(Phase::Failed),
+                _ => self.enter(Phase::Finished),
+            }
+        }
+        log::debug!(
+            "Fetching result for {} / {} ...",
+            self.flow.client_id,
+            self.flow.flow_id
+        );
+        loop {
+            let result = match artifact {
+                Some(artifact) => self.flow.artifact_results::<T>(artifact).await?,
+                None => self.flow.results::<T>().await?,
+            };
+            if !result.is_empty() {
+                log::debug!("Done!");
+                return Ok(result);
+            }
+            if self.phase == Phase::Failed {
+                return Err(APIClientError::FlowFailed(self.flow.flow_id.clone()));
+            }
+            let collected = self.flow.summary().await?.map(|s| s.total_collected_rows);
+            if collected == Some(0) {
+                return Ok(result);
+            }
+            log::trace!("zZz...");
+            self.pause().await;
+        }
+    }
+
+    /// Wait for the flow's log to become non-empty, and return it
+    pub async fn log(&mut self) -> Result<Vec<FlowLogEntry>, APIClientError> {
+        loop {
+            let result = self.flow.log().await?;
+            if !result.is_empty() {
+                for r in &result {
+                    log::debug!(
+                        "flow_log({}/{}): {} {}: {}",
+                        self.flow.client_id,
+                        self.flow.flow_id,
+                        r.client_time,
+                        r.level,
+                        r.message
+                    );
+                }
+                return Ok(result);
+            }
+            self.pause().await;
+            log::debug!("Retrying...");
+        }
+    }
+}
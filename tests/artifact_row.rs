@@ -0,0 +1,71 @@
+use serde_json::json;
+use time::OffsetDateTime;
+use velociraptor_api::{ArtifactRow, VrTimestamp};
+
+#[derive(Debug, ArtifactRow)]
+struct Row {
+    os_path: String,
+    #[artifact(rename = "UUID", alias = "Guid")]
+    uuid: String,
+    #[artifact(default)]
+    size: u64,
+    #[artifact(timestamp)]
+    mtime: OffsetDateTime,
+    #[artifact(base64)]
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Default, ArtifactRow)]
+#[artifact(default, rename_all = "snake_case")]
+struct Defaults {
+    client_id: String,
+    last_seen: VrTimestamp,
+}
+
+#[test]
+fn pascal_case_columns() {
+    let row: Row = serde_json::from_value(json!({
+        "OsPath": "/etc/passwd",
+        "UUID": "1234",
+        "Mtime": 1_700_000_000_000u64,
+        "Data": "aGVsbG8=",
+    }))
+    .unwrap();
+    assert_eq!(row.os_path, "/etc/passwd");
+    assert_eq!(row.uuid, "1234");
+    assert_eq!(row.size, 0);
+    assert_eq!(row.mtime.unix_timestamp(), 1_700_000_000);
+    assert_eq!(row.data, b"hello");
+}
+
+#[test]
+fn alias_and_null_default() {
+    let row: Row = serde_json::from_value(json!({
+        "OsPath": "",
+        "Guid": "5678",
+        "Size": null,
+        "Mtime": "2023-11-14T22:13:20Z",
+        "Data": "",
+    }))
+    .unwrap();
+    assert_eq!(row.uuid, "5678");
+    assert_eq!(row.size, 0);
+    assert_eq!(row.mtime.unix_timestamp(), 1_700_000_000);
+}
+
+#[test]
+fn missing_column() {
+    let err = serde_json::from_value::<Row>(json!({ "OsPath": "" })).unwrap_err();
+    assert!(err.to_string().contains("UUID"), "{err}");
+}
+
+#[test]
+fn struct_default() {
+    let row: Defaults = serde_json::from_value(json!({ "client_id": null })).unwrap();
+    assert_eq!(row.client_id, "");
+    assert!(row.last_seen.is_unset());
+    let row: Defaults =
+        serde_json::from_value(json!({ "client_id": "C.1", "last_seen": 1_700_000_000 })).unwrap();
+    assert_eq!(row.client_id, "C.1");
+    assert_eq!(row.last_seen.0.unix_timestamp(), 1_700_000_000);
+}
@@ -0,0 +1,344 @@
+#![cfg(feature = "test-util")]
+
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::json;
+use velociraptor_api::builtin::ClientInfo;
+use velociraptor_api::mock::MockServer;
+use velociraptor_api::{APIClientError, QueryOptions, VfsPath};
+
+const ARTIFACT: &str = r#"
+name: Custom.Test
+parameters:
+  - name: Count
+    type: int
+sources:
+  - query: SELECT * FROM info()
+"#;
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Row {
+    #[serde(rename = "Name")]
+    name: String,
+}
+
+#[tokio::test]
+async fn sync_query() {
+    let server = MockServer::start().await.unwrap();
+    server.on_query("FROM info()", vec![json!({ "Name": "host" })]);
+    let client = server.client();
+    let rows: Vec<Row> = client
+        .sync_query(
+            "SELECT Hostname AS Name FROM info()",
+            &QueryOptions::builder().build(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        rows,
+        [Row {
+            name: "host".into()
+        }]
+    );
+    assert_eq!(server.queries(), ["SELECT Hostname AS Name FROM info()"]);
+}
+
+#[tokio::test]
+async fn collect_and_wait() {
+    let server = MockServer::start().await.unwrap();
+    server.add_artifact(ARTIFACT).expect_collection(
+        "F.1",
+        &["RUNNING", "RUNNING", "FINISHED"],
+        vec![json!({ "Name": "a" }), json!({ "Name": "b" })],
+    );
+    let client = server.client();
+    let client = client.new_client_unchecked("C.1");
+    let flow = client
+        .collect("Custom.Test", &[("Count".into(), "2".into())])
+        .await
+        .unwrap();
+    let mut waiter = flow.waiter();
+    assert!(!waiter.is_finished());
+    let rows: Vec<Row> = waiter.results().await.unwrap();
+    assert!(waiter.is_finished());
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1].name, "b");
+}
+
+#[tokio::test]
+async fn collect_invalid_parameter() {
+    let server = MockServer::start().await.unwrap();
+    server.add_artifact(ARTIFACT);
+    let client = server.client();
+    let client = client.new_client_unchecked("C.1");
+    let result = client
+        .collect("Custom.Test", &[("Count".into(), "two".into())])
+        .await;
+    assert!(matches!(result, Err(APIClientError::InvalidParameters(..))));
+    assert!(!server
+        .queries()
+        .iter()
+        .any(|q| q.contains("collect_client(")));
+}
+
+#[tokio::test]
+async fn fetch() {
+    let server = MockServer::start().await.unwrap();
+    let data: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+    server.add_file("downloads/C.1/F.1/data.zip", &data);
+    let client = server.client();
+    assert_eq!(
+        client.fetch("downloads/C.1/F.1/data.zip").await.unwrap(),
+        data
+    );
+    assert!(client.fetch("downloads/missing").await.is_err());
+}
+
+#[tokio::test]
+async fn stat() {
+    let server = MockServer::start().await.unwrap();
+    server.add_file("downloads/C.1/F.1/data.zip", b"data");
+    let client = server.client();
+    let stat = client.stat("downloads/C.1/F.1/data.zip").await.unwrap();
+    assert_eq!(stat.size, 4);
+    assert!(stat.hashes.is_none());
+    let err = client.stat("downloads/missing").await.unwrap_err();
+    assert!(
+        matches!(err, APIClientError::IO(ref e) if e.kind() == std::io::ErrorKind::NotFound),
+        "{err}"
+    );
+}
+
+#[tokio::test]
+async fn fetch_with_progress() {
+    let server = MockServer::start().await.unwrap();
+    let data: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+    server.add_file("downloads/C.1/F.1/data.zip", &data);
+    let client = server.client();
+    let mut output = vec![];
+    client
+        .fetch_to("downloads/C.1/F.1/data.zip", &mut output)
+        .await
+        .unwrap();
+    assert_eq!(output, data);
+    assert!(server.queries().is_empty());
+
+    let mut output = vec![];
+    let mut reported = vec![];
+    let size = client
+        .fetch_to_with_progress("downloads/C.1/F.1/data.zip", &mut output, |done, total| {
+            reported.push((done, total))
+        })
+        .await
+        .unwrap();
+    assert_eq!(size, 10_000);
+    assert_eq!(output, data);
+    assert_eq!(reported.last(), Some(&(10_000, Some(10_000))));
+
+    let err = client
+        .fetch_to_with_progress("downloads/missing", &mut vec![], |_, _| {})
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, APIClientError::IO(ref e) if e.kind() == std::io::ErrorKind::NotFound),
+        "{err}"
+    );
+}
+
+#[tokio::test]
+async fn fetch_parallel() {
+    let server = MockServer::start().await.unwrap();
+    let data: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+    server.add_file("downloads/C.1/F.1/data.zip", &data);
+    let client = server.client();
+    let mut output = vec![];
+    let size = client
+        .fetch_parallel_to("downloads/C.1/F.1/data.zip", &mut output, 4, |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(size, 10_000);
+    assert_eq!(output, data);
+
+    // The file ends before the size reported by stat()
+    server.on_query("FROM stat(", vec![json!({ "Size": 20_000, "Mtime": null })]);
+    let err = client
+        .fetch_parallel_to("downloads/C.1/F.1/data.zip", &mut vec![], 4, |_, _| {})
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, APIClientError::IO(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof),
+        "{err}"
+    );
+}
+
+#[tokio::test]
+async fn upload() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+    let data: Vec<u8> = (0..=255).cycle().take(5_000).collect();
+    let written = client
+        .upload("public/data.bin", &mut data.as_slice())
+        .await
+        .unwrap();
+    assert_eq!(written, data.len() as u64);
+    assert_eq!(server.file("public/data.bin").unwrap(), data);
+    assert_eq!(client.fetch("public/data.bin").await.unwrap(), data);
+
+    client
+        .upload("public/data.bin", &mut &b"replaced"[..])
+        .await
+        .unwrap();
+    assert_eq!(server.file("public/data.bin").unwrap(), b"replaced");
+}
+
+#[tokio::test]
+async fn upload_outside_directory() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+    let err = client
+        .upload(VfsPath::new(["public", ".."]), &mut &b"data"[..])
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, APIClientError::IO(ref e) if e.kind() == std::io::ErrorKind::InvalidInput),
+        "{err}"
+    );
+    assert!(server.queries().is_empty());
+}
+
+#[tokio::test]
+async fn fetch_verified() {
+    let server = MockServer::start().await.unwrap();
+    let path = VfsPath::uploads("C.1", "F.1").join("auto").join("data.bin");
+    server.add_file(&path, b"data").on_query(
+        "FROM uploads(",
+        vec![json!({
+            "_Components": path.components(),
+            "vfs_path": "/C:/data.bin",
+            "sha256": "3a6eb0790f39ac87c94f3856b2dd2c5d110e6811602261a9a923d3bb23adc8b7",
+        })],
+    );
+    let client = server.client();
+    let verified = client
+        .fetch_verified_to(&path, &mut vec![], |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(verified.size, 4);
+    assert_eq!(verified.expected.as_deref(), Some(verified.sha256.as_str()));
+
+    server.add_file(&path, b"tampered");
+    let err = client
+        .fetch_verified_to(&path, &mut vec![], |_, _| {})
+        .await
+        .unwrap_err();
+    assert!(matches!(err, APIClientError::Integrity(_)), "{err}");
+
+    // Files outside of flow uploads have no recorded hash
+    server.add_file("public/data.bin", b"data");
+    let verified = client
+        .fetch_verified_to("public/data.bin", &mut vec![], |_, _| {})
+        .await
+        .unwrap();
+    assert_eq!(verified.expected, None);
+    assert_eq!(
+        server
+            .queries()
+            .iter()
+            .filter(|q| q.contains("FROM uploads("))
+            .count(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn notebook_attachments() {
+    let server = MockServer::start().await.unwrap();
+    let client = server.client();
+    let notebook = client.notebook_unchecked("N.F.ABC-C.1");
+    let path = notebook
+        .upload_attachment("image.png", &mut &b"png"[..])
+        .await
+        .unwrap();
+    notebook
+        .upload_attachment("data.csv", &mut &b"a,b"[..])
+        .await
+        .unwrap();
+    assert_eq!(
+        path,
+        VfsPath::notebook_attachments("N.F.ABC-C.1").join("image.png")
+    );
+    let attachments = notebook.attachments().await.unwrap();
+    let names: Vec<_> = attachments.iter().map(|a| a.name.as_str()).collect();
+    assert_eq!(names, ["data.csv", "image.png"]);
+    assert_eq!(
+        notebook.download_attachment("data.csv").await.unwrap(),
+        b"a,b"
+    );
+}
+
+#[tokio::test]
+async fn empty_flow() {
+    let server = MockServer::start().await.unwrap();
+    server
+        .add_artifact(ARTIFACT)
+        .expect_collection("F.3", &["RUNNING", "FINISHED"], vec![]);
+    let client = server.client();
+    let client = client.new_client_unchecked("C.1");
+    let flow = client.collect("Custom.Test", &[]).await.unwrap();
+    let rows: Vec<Row> = tokio::time::timeout(Duration::from_secs(5), flow.fetch())
+        .await
+        .expect("empty results are returned")
+        .unwrap();
+    assert!(rows.is_empty());
+}
+
+#[tokio::test]
+async fn failed_flow() {
+    let server = MockServer::start().await.unwrap();
+    server
+        .add_artifact(ARTIFACT)
+        .expect_collection("F.2", &["RUNNING", "ERROR"], vec![]);
+    let client = server.client();
+    let client = client.new_client_unchecked("C.1");
+    let flow = client.collect("Custom.Test", &[]).await.unwrap();
+    let err = flow.waiter().results::<Row>().await.unwrap_err();
+    assert!(
+        matches!(err, APIClientError::FlowFailed(ref id) if id == "F.2"),
+        "{err}"
+    );
+}
+
+#[tokio::test]
+async fn builtin_source() {
+    let server = MockServer::start().await.unwrap();
+    server
+        .add_artifact("name: Generic.Client.Info")
+        .expect_collection(
+            "F.4",
+            &["FINISHED"],
+            vec![json!({
+                "Name": "velociraptor",
+                "Version": "0.7.0",
+                "BuildTime": "",
+                "Labels": null,
+                "Hostname": "host",
+                "OS": "linux",
+                "Architecture": "amd64",
+                "Platform": "debian",
+                "PlatformVersion": "12",
+                "KernelVersion": "6.1",
+                "Fqdn": "host.example.com",
+            })],
+        );
+    let client = server.client();
+    let client = client.new_client_unchecked("C.1");
+    let flow = client.collect_builtin(&ClientInfo).await.unwrap();
+    let rows = flow.fetch_builtin::<ClientInfo>().await.unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].os, "linux");
+    assert!(server
+        .queries()
+        .iter()
+        .any(|q| q.contains("flow_results(") && q.contains("artifact=artifact")));
+}